@@ -1,7 +1,6 @@
-#![feature(test)]
-#![feature(or_patterns)]
+#![cfg_attr(test, feature(test))]
 
-mod markdown;
+pub mod markdown;
 mod utils;
 
 use wasm_bindgen::prelude::*;
@@ -26,4 +25,9 @@ impl Compiler {
         let doc = markdown::parse(source);
         markdown::json::render(source, doc)
     }
+
+    pub fn compile_vdom(&self, source: &str) -> String {
+        let doc = markdown::parse(source);
+        markdown::vdom::render(source, doc)
+    }
 }
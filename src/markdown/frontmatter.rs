@@ -0,0 +1,218 @@
+use std::io::BufRead;
+
+use serde_json::{Map, Value};
+
+/// The most `extract_front_matter` reads looking for the end of a block, so
+/// an unclosed opener can't make it read the whole input.
+pub const MAX_FRONT_MATTER: u64 = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrontMatterFormat {
+    /// Between `---` fences, as used by Jekyll.
+    Yaml,
+    /// Between `+++` fences, as used by Hugo.
+    Toml,
+    /// An object from a first line starting with `{` to the line ending in
+    /// the matching `}`, which may be the first line. The braces are kept in
+    /// the content, which has to parse as a JSON object.
+    Json,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct FrontMatter {
+    pub format: FrontMatterFormat,
+    pub content: String,
+}
+
+/// Read the front matter at the start of `reader` without reading any of
+/// the body after its closing line. The format is chosen by the first line.
+/// Returns `None` after that line if it doesn't open front matter, or if the
+/// block isn't closed within `MAX_FRONT_MATTER` bytes.
+pub fn extract_front_matter<R: BufRead>(reader: R) -> Option<FrontMatter> {
+    let mut reader = reader.take(MAX_FRONT_MATTER);
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    let (format, close) = match line.trim_end() {
+        "---" => (FrontMatterFormat::Yaml, "---"),
+        "+++" => (FrontMatterFormat::Toml, "+++"),
+        l if l.starts_with('{') => return json_front_matter(reader, line),
+        _ => return None,
+    };
+
+    let mut content = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).ok()? == 0 {
+            return None;
+        }
+        if line.trim_end() == close {
+            return Some(FrontMatter { format, content });
+        }
+        content.push_str(&line);
+    }
+}
+
+// Reads on from the opening `line` until the brace that closes the object.
+fn json_front_matter<R: BufRead>(mut reader: R, mut line: String) -> Option<FrontMatter> {
+    let mut scan = JsonScan::default();
+    let mut content = String::new();
+    loop {
+        if let Some(end) = scan.close(&line) {
+            if !line[end..].trim().is_empty() {
+                return None;
+            }
+            content.push_str(&line);
+            serde_json::from_str::<Map<String, Value>>(&content).ok()?;
+            return Some(FrontMatter {
+                format: FrontMatterFormat::Json,
+                content,
+            });
+        }
+        content.push_str(&line);
+        line.clear();
+        if reader.read_line(&mut line).ok()? == 0 {
+            return None;
+        }
+    }
+}
+
+// Brace depth across lines, ignoring braces inside strings.
+#[derive(Default)]
+struct JsonScan {
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+}
+
+impl JsonScan {
+    // Index just past the `}` that brings the depth back to 0, if it's on
+    // this line.
+    fn close(&mut self, line: &str) -> Option<usize> {
+        for (i, c) in line.char_indices() {
+            if self.in_string {
+                match c {
+                    _ if self.escaped => self.escaped = false,
+                    '\\' => self.escaped = true,
+                    '"' => self.in_string = false,
+                    _ => {}
+                }
+                continue;
+            }
+            match c {
+                '"' => self.in_string = true,
+                '{' => self.depth += 1,
+                '}' => {
+                    self.depth -= 1;
+                    if self.depth == 0 {
+                        return Some(i + 1);
+                    }
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn front_matter(format: FrontMatterFormat, content: &str) -> Option<FrontMatter> {
+        Some(FrontMatter {
+            format,
+            content: content.into(),
+        })
+    }
+
+    #[test]
+    fn test_front_matter() {
+        let mut reader = "---\ntitle: A\ndate: 2020-08-07\n---\n# Body\n".as_bytes();
+        assert_eq!(
+            extract_front_matter(&mut reader),
+            front_matter(FrontMatterFormat::Yaml, "title: A\ndate: 2020-08-07\n")
+        );
+        assert_eq!(reader, b"# Body\n");
+
+        let mut reader = "---\r\n---\r\nBody".as_bytes();
+        assert_eq!(
+            extract_front_matter(&mut reader),
+            front_matter(FrontMatterFormat::Yaml, "")
+        );
+        assert_eq!(reader, b"Body");
+    }
+
+    #[test]
+    fn test_toml_front_matter() {
+        let mut reader = "+++\ntitle = \"A\"\n+++\n# Body\n".as_bytes();
+        assert_eq!(
+            extract_front_matter(&mut reader),
+            front_matter(FrontMatterFormat::Toml, "title = \"A\"\n")
+        );
+        assert_eq!(reader, b"# Body\n");
+
+        // A TOML block is not closed by a YAML fence
+        assert_eq!(extract_front_matter("+++\na = 1\n---\n".as_bytes()), None);
+    }
+
+    #[test]
+    fn test_json_front_matter() {
+        let mut reader = "{\n  \"title\": \"A\"\n}\n# Body\n".as_bytes();
+        assert_eq!(
+            extract_front_matter(&mut reader),
+            front_matter(FrontMatterFormat::Json, "{\n  \"title\": \"A\"\n}\n")
+        );
+        assert_eq!(reader, b"# Body\n");
+
+        // Only at the very start of the document
+        assert_eq!(extract_front_matter(" {\n}\n".as_bytes()), None);
+        assert_eq!(extract_front_matter("text\n{\n}\n".as_bytes()), None);
+    }
+
+    #[test]
+    fn test_json_front_matter_close() {
+        // Closed on the opening line
+        let mut reader = "{\"title\": \"A\"}\n# Body\n".as_bytes();
+        assert_eq!(
+            extract_front_matter(&mut reader),
+            front_matter(FrontMatterFormat::Json, "{\"title\": \"A\"}\n")
+        );
+        assert_eq!(reader, b"# Body\n");
+
+        // Closed by a trailing brace, past nested objects and braces in strings
+        let source = "{\n  \"a\": {\"b\": \"}\\\"{\"},\n  \"c\": 1}\nBody";
+        let mut reader = source.as_bytes();
+        assert_eq!(
+            extract_front_matter(&mut reader),
+            front_matter(FrontMatterFormat::Json, &source[..source.len() - 4])
+        );
+        assert_eq!(reader, b"Body");
+
+        // Braces that aren't a JSON object, like a Hugo shortcode
+        assert_eq!(extract_front_matter("{{< note >}}\n".as_bytes()), None);
+        assert_eq!(extract_front_matter("{\"a\": 1} text\n".as_bytes()), None);
+    }
+
+    #[test]
+    fn test_front_matter_limit() {
+        let source = format!("{{\n{}", "\"a\": 1,\n".repeat(100_000));
+        let mut reader = source.as_bytes();
+        assert_eq!(extract_front_matter(&mut reader), None);
+        assert_eq!(reader.len() as u64, source.len() as u64 - MAX_FRONT_MATTER);
+
+        let source = format!("---\n{}", "a: 1\n".repeat(100_000));
+        let mut reader = source.as_bytes();
+        assert_eq!(extract_front_matter(&mut reader), None);
+        assert!(!reader.is_empty());
+    }
+
+    #[test]
+    fn test_no_front_matter() {
+        let mut reader = "# Title\n---\nBody\n---\n".as_bytes();
+        assert_eq!(extract_front_matter(&mut reader), None);
+        assert_eq!(reader, b"---\nBody\n---\n");
+
+        assert_eq!(extract_front_matter("---\nnever closed\n".as_bytes()), None);
+        assert_eq!(extract_front_matter("".as_bytes()), None);
+    }
+}
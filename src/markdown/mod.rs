@@ -1,11 +1,74 @@
+mod frontmatter;
 mod parse;
 mod render;
+mod strict;
+mod tree;
 
+pub use frontmatter::{extract_front_matter, FrontMatter, FrontMatterFormat, MAX_FRONT_MATTER};
 pub use parse::md_parser;
+pub use parse::token;
 pub use parse::{Kind, Node};
+pub use strict::{try_parse, ParseError, StrictConfig};
+pub use tree::{BlockToken, Descendants, Section};
 
-pub fn parse(source: &str) -> Node {
-    parse::parse(source)
+pub fn parse<S: AsRef<str>>(source: S) -> Node {
+    parse::parse(source.as_ref())
 }
 
-pub use render::json;
+/// Parse `source`, also returning the tokens the parser ran over. These
+/// cover the whole source without gaps.
+pub fn parse_with_tokens<S: AsRef<str>>(source: S) -> (Node, Vec<token::Token>) {
+    parse::parse_with_tokens(source.as_ref())
+}
+
+/// Parse `source` keeping at most `max_nodes` nodes in the returned tree,
+/// along with whether any were dropped. Only a prefix of `source`, cut at a
+/// line boundary and grown until the budget is hit, is tokenized and parsed,
+/// so the work done is bounded by `max_nodes` rather than the source length.
+/// When truncated, every container that lost nodes ends at its last kept
+/// child, so the document and the blocks that were cut end earlier than
+/// in a full parse.
+pub fn parse_bounded<S: AsRef<str>>(source: S, max_nodes: usize) -> (Node, bool) {
+    parse::parse_bounded(source.as_ref(), max_nodes)
+}
+
+/// Flat list of the blocks in `source` with their line ranges, for lint
+/// rules that only care about block structure. This costs a full parse: the
+/// outline is read off the tree so that it agrees with the parser on lazy
+/// continuation and nesting, and the parser does no separate inline pass to
+/// skip.
+pub fn block_outline<S: AsRef<str>>(source: S) -> Vec<BlockToken> {
+    let source = source.as_ref();
+    parse(source).block_outline(source)
+}
+
+pub use render::{json, mdast, render, vdom, OutputFormat};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::borrow::Cow;
+
+    #[test]
+    fn test_parse_as_ref() {
+        let expected = parse("# A");
+        let owned = String::from("# A");
+        assert_eq!(parse(&owned), expected);
+        assert_eq!(parse(Cow::Borrowed("# A")), expected);
+        assert_eq!(parse(owned), expected);
+    }
+
+    #[test]
+    fn test_parse_with_tokens() {
+        let source = "# A  \n\n> * b\n>   1. c\n\ntext\t\n";
+        let (doc, tokens) = parse_with_tokens(source);
+        assert_eq!(doc, parse(source));
+
+        let mut end = 0;
+        for t in &tokens {
+            assert_eq!(t.span().0, end);
+            end = t.span().1;
+        }
+        assert_eq!(end, source.len());
+    }
+}
@@ -1,9 +1,8 @@
-mod token;
+pub mod token;
 
-use peg;
 use token::{Span, Token, Tokenizer};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Kind {
     // Container block tokens
     Document,
@@ -11,7 +10,7 @@ pub enum Kind {
     Empty,
     UnorderedList(bool),
     OrderedList(bool),
-    ListItem,
+    ListItem(Span),
     // Leaf block tokens
     Heading(usize),
     Paragraph,
@@ -21,6 +20,24 @@ pub enum Kind {
     Whitespace,
 }
 
+impl Kind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Kind::Document => "Document",
+            Kind::BlockQuote => "BlockQuote",
+            Kind::Empty => "Empty",
+            Kind::UnorderedList(..) => "UnorderedList",
+            Kind::OrderedList(..) => "OrderedList",
+            Kind::ListItem(..) => "ListItem",
+            Kind::Heading(..) => "Heading",
+            Kind::Paragraph => "Paragraph",
+            Kind::EmptyLine => "EmptyLine",
+            Kind::Plaintext => "Plaintext",
+            Kind::Whitespace => "Whitespace",
+        }
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub struct Node {
     pub kind: Kind,
@@ -56,6 +73,8 @@ peg::parser! {
                 Node::new_block(Kind::Document, 0, end, children)
             }
 
+        // Block openers are tried in priority order and the first that
+        // matches a line wins; a paragraph is only tried when none do.
         rule block() -> Vec<Node>
             = a:blank_lines_eof()? b:(
                 c:heading() /
@@ -76,28 +95,35 @@ peg::parser! {
             }
 
         // Heading
+        // Interior whitespace is kept, trailing whitespace is not
         rule atx_inline() -> Node
-            = !newline() x:(
-                non_continuing_text()
-            ) { x }
+            = a:(plaintext() / w:whitespace() &plaintext() { w })+ {
+              let (start, _) = a.first().unwrap().span;
+              let (_, end) = a.last().unwrap().span;
+              Node::new(Kind::Plaintext, start, end)
+            }
         rule atx_start() -> Span
             = a:$([Token::Hash((a, b)) if (b - a) <= 6]) { a[0].span() }
+        // A line that really opens a heading, unlike a bare `#tag`
+        rule atx_opener()
+            = atx_start() &([Token::Whitespace(..) | Token::Newline(..)] / eof())
         rule atx_empty() -> Vec<Node>
             = s:atx_start() t:sp() !atx_inline() b:blank_lines_eof() {
-                let (x, start) = t.unwrap_or(s);
-                let (_, end) = (&b).as_ref().map(|b| b.span).unwrap_or((x, start));
-                let n = Node::new(Kind::Heading(s.1 - s.0), start, end);
+                // Empty, so the span ends where it starts, before the
+                // blank line that follows
+                let (_, start) = t.unwrap_or(s);
+                let n = Node::new(Kind::Heading(s.1 - s.0), start, start);
                 match b {
                     Some(b) => vec![n, b],
                     None => vec![n],
                 }
             }
         rule atx_heading() -> Vec<Node>
-            = s:atx_start() t:ws() a:atx_inline()* b:blank_lines_eof() {
+            = s:atx_start() t:ws() a:atx_inline()*
+              b:(blank_lines_eof() / ws() eof() { None }) {
                 let (_, x) = t;
-                let (_, y) = (&b).as_ref().map(|b| b.span).unwrap_or(t);
                 let start = a.first().map(|a| a.span.0).unwrap_or(x);
-                let end = a.last().map(|y| y.span.1).unwrap_or(y);
+                let end = a.last().map(|y| y.span.1).unwrap_or(start);
                 let n = Node::new_block(Kind::Heading(s.1 - s.0), start, end, a);
                 match b {
                     Some(b) => vec![n, b],
@@ -145,37 +171,62 @@ peg::parser! {
               }
 
         // List
-        rule bullet() -> usize
+        // Returns the marker span and the width of the content indent
+        rule bullet() -> (Span, usize)
             = a:non_indent_space()
               b:$([Token::Plus(..) | Token::Asterisk(..) | Token::Dash(..)])
               c:whitespace() {
                 let (a, _) = a.unwrap_or(b[0].span());
                 let (_, c) = c.span;
-                c - a
+                (b[0].span(), c - a)
               }
-        rule enumerator() -> usize
+        rule enumerator() -> (Span, usize)
             = a:non_indent_space()
               b:$([Token::NumDot(..) | Token::NumParen(..)])
               c:whitespace() {
                 let (a, _) = a.unwrap_or(b[0].span());
                 let (_, c) = c.span;
-                c - a
+                (b[0].span(), c - a)
+              }
+        // A change of marker type starts a new list
+        rule marker(ol: bool) -> (Span, usize)
+            = w:enumerator() {? if ol { Ok(w) } else { Err("bullet") } } /
+              w:bullet() {? if ol { Err("enumerator") } else { Ok(w) } } /
+              empty_marker(ol)
+        // A marker directly followed by the end of the line opens an empty
+        // item. Unlike bullet() and enumerator() it can't interrupt a paragraph
+        rule empty_marker(ol: bool) -> (Span, usize)
+            = a:non_indent_space()
+              b:$([
+                Token::Plus(..) | Token::Asterisk(..) | Token::Dash(..) |
+                Token::NumDot(..) | Token::NumParen(..)
+              ])
+              &([Token::Newline(..)] / eof()) {?
+                let (a, _) = a.unwrap_or(b[0].span());
+                let (_, c) = b[0].span();
+                match (ol, b[0]) {
+                    (true, Token::NumDot(..) | Token::NumParen(..)) |
+                    (false, Token::Plus(..) | Token::Asterisk(..) | Token::Dash(..)) => {
+                        Ok((b[0].span(), c - a + 1))
+                    },
+                    _ => Err("marker"),
+                }
               }
         rule unordered_list() -> Vec<Node>
-            = &bullet()
+            = &marker(false)
               a:(
                 b:list_tight(false, false) /
                 b:list_loose(false, true)
               ) { a }
         rule ordered_list() -> Vec<Node>
-            = &enumerator()
+            = &marker(true)
               a:(
                 b:list_tight(true, false) /
                 b:list_loose(true, true)
               ) { a }
         rule list_tight(ol: bool, loose: bool) -> Vec<Node>
-            = a:(list_item_tight())+
-              b:blank_lines_eof()? !bullet() !enumerator() {
+            = a:(list_item_tight(ol))+
+              b:blank_lines_eof()? !marker(ol) {
                 let (start, _) = a.first().unwrap().span;
                 let (_, end) = a.last().unwrap().span;
                 let kind = match ol {
@@ -189,7 +240,7 @@ peg::parser! {
                 }
               }
         rule list_loose(ol: bool, loose: bool) -> Vec<Node>
-            = a:list_item()+
+            = a:list_item(ol)+
              {
                 let (start, _) = a.first().unwrap().span;
                 let (_, end) = a.last().unwrap().span;
@@ -199,14 +250,15 @@ peg::parser! {
                 };
                 vec![Node::new_block(kind, start, end, a)]
               }
-        rule list_item() -> Node
-            = width:(bullet() / enumerator())
-              a:list_block()
-              b:(list_continuation_block(width)*)
+        rule list_item(ol: bool) -> Node
+            = m:marker(ol)
+              a:(list_block() / eof() { vec![] })
+              b:(list_continuation_block((m.1))*)
               c:blank_lines_eof()? {
                 let s = [a, b.into_iter().flatten().collect()].concat();
-                let (start, _) = s.first().unwrap().span();
-                let (_, end) = s.last().unwrap().span();
+                let (_, m_end) = m.0;
+                let (start, _) = s.first().map(|t| t.span()).unwrap_or((m_end, m_end));
+                let (_, end) = s.last().map(|t| t.span()).unwrap_or((m_end, m_end));
                 let mut sub = md_parser::doc(&s).unwrap();
                 let (children, end) = match c.flatten() {
                     Some(c) => {
@@ -216,18 +268,19 @@ peg::parser! {
                     },
                     None => (sub.children, end)
                 };
-                Node::new_block(Kind::ListItem, start, end, children)
+                Node::new_block(Kind::ListItem(m.0), start, end, children)
               }
-        rule list_item_tight() -> Node
-            = width:(bullet() / enumerator())
-              a:list_block()
-              b:(!blank_line() c:list_continuation_block(width) { c })*
-              !list_continuation_block(width) {
+        rule list_item_tight(ol: bool) -> Node
+            = m:marker(ol)
+              a:(list_block() / eof() { vec![] })
+              b:(!blank_line() c:list_continuation_block((m.1)) { c })*
+              !list_continuation_block((m.1)) {
                 let s = [a, b.into_iter().flatten().collect()].concat();
-                let (start, _) = s.first().unwrap().span();
-                let (_, end) = s.last().unwrap().span();
+                let (_, m_end) = m.0;
+                let (start, _) = s.first().map(|t| t.span()).unwrap_or((m_end, m_end));
+                let (_, end) = s.last().map(|t| t.span()).unwrap_or((m_end, m_end));
                 let sub = md_parser::doc(&s).unwrap();
-                Node::new_block(Kind::ListItem, start, end, sub.children)
+                Node::new_block(Kind::ListItem(m.0), start, end, sub.children)
               }
         rule list_block() -> Vec<Token>
             = a:line() b:list_block_line()* {
@@ -253,7 +306,9 @@ peg::parser! {
                 }
             }
         rule list_block_line() -> Vec<Token>
-            = !blank_line() !(sp() (bullet() / enumerator()))
+            = !blank_line() !(sp() (marker(false) / marker(true)))
+              !block_quote_start()
+              !atx_opener()
               // !horizonatal_rule()
               a:line()
               { a }
@@ -279,19 +334,20 @@ peg::parser! {
             = v:(!end_line() b:inline()+ { b })+
             end_line()?
             eof()?
-            { v.into_iter().flatten().collect() }
-        rule inline() -> Node
+            { v.into_iter().flatten().flatten().collect() }
+        rule inline() -> Vec<Node>
             = text()
 
-        rule text() -> Node
-            = a:(
-                non_continuing_text() /
-                continuation()
-            )+ {
-              let (start, _) = a.first().unwrap().span;
-              let (_, end) = a.last().unwrap().span;
-              Node::new(Kind::Plaintext, start, end)
+        // One Plaintext per line, joined by the Whitespace of each line
+        // break. The container markers and indentation that the parser
+        // skipped on continuation lines are left out of every node's span
+        rule text() -> Vec<Node>
+            = a:non_continuing_text()
+              b:(c:continuation() d:line_text() { vec![c, d] })* {
+                std::iter::once(a).chain(b.into_iter().flatten()).collect()
             }
+        rule line_text() -> Node
+            = (ws() &plaintext())? a:non_continuing_text() { a }
         rule non_continuing_text() -> Node
             = a:(
                 plaintext() /
@@ -350,7 +406,7 @@ peg::parser! {
               // The next line must not start with a block opener
               !blank_line()
               !block_quote_start()
-              !atx_start()
+              !atx_opener()
               !bullet()
               !enumerator() {
                 a.map(|span| {
@@ -411,18 +467,51 @@ peg::parser! {
         rule line() -> Vec<Token>
             = a:(
                 b:$((![Token::Newline(..)] [_])*) c:$([Token::Newline(..)]) {
-                    Vec::from([b, c].concat())
+                    [b, c].concat()
                 } /
                 b:$([_]+) eof() { Vec::from(b) }
               )
-              { a.into() }
+              { a }
     }
 }
 
 pub fn parse(source: &str) -> Node {
+    parse_with_tokens(source).0
+}
+
+pub fn parse_with_tokens(source: &str) -> (Node, Vec<Token>) {
     let tokenizer = Tokenizer::new(0, source);
     let tokens = tokenizer.collect::<Vec<_>>();
-    md_parser::doc(&tokens).unwrap()
+    (md_parser::doc(&tokens).unwrap(), tokens)
+}
+
+pub fn parse_bounded(source: &str, max_nodes: usize) -> (Node, bool) {
+    let mut tokenizer = Tokenizer::new(0, source);
+    let mut tokens = Vec::new();
+    let mut newlines = 0;
+    let mut lines = max_nodes.max(1);
+    loop {
+        // The prefix doubles each round, so the repeated parses cost at most
+        // about twice a single parse of the final prefix
+        let mut exhausted = true;
+        for t in &mut tokenizer {
+            tokens.push(t);
+            if let Token::Newline(_) = t {
+                newlines += 1;
+                if newlines == lines {
+                    exhausted = false;
+                    break;
+                }
+            }
+        }
+
+        let mut doc = md_parser::doc(&tokens).unwrap();
+        let truncated = doc.truncate(max_nodes);
+        if truncated || exhausted {
+            return (doc, truncated);
+        }
+        lines *= 2;
+    }
 }
 
 #[cfg(test)]
@@ -440,13 +529,13 @@ mod test {
 
     macro_rules! empty {
         ($start:literal $end:literal $($child:expr )*) => (
-           Node::new_block(Kind::Empty, $start, $end, vec![$($child),*]);
+           Node::new_block(Kind::Empty, $start, $end, vec![$($child),*])
         );
     }
 
     macro_rules! empty_line {
         ($start:literal $end:literal $($child:expr )*) => (
-           Node::new_block(Kind::EmptyLine, $start, $end, vec![$($child),*]);
+           Node::new_block(Kind::EmptyLine, $start, $end, vec![$($child),*])
         );
     }
 
@@ -504,8 +593,8 @@ mod test {
     }
 
     macro_rules! li {
-        ($start:literal $end:literal $($child:expr )*) => {
-            Node::new_block(Kind::ListItem, $start, $end, vec![$($child),*])
+        ($marker:tt $start:literal $end:literal $($child:expr )*) => {
+            Node::new_block(Kind::ListItem($marker), $start, $end, vec![$($child),*])
         };
     }
 
@@ -520,14 +609,114 @@ mod test {
         assert_eq!(parse("   \n"), doc!(0 4 empty!(0 4 empty_line!(0 4))));
     }
 
+    #[test]
+    fn test_blank_line_run() {
+        assert_eq!(
+            parse("a\n\n\n\nb"),
+            doc!(0 6
+                p!(0 1 plain!(0 1))
+                empty!(1 5 empty_line!(1 2) empty_line!(2 3) empty_line!(3 4) empty_line!(4 5))
+                p!(5 6 plain!(5 6))
+            )
+        );
+    }
+
+    #[test]
+    fn test_whitespace_only_line() {
+        assert_eq!(
+            parse("a\n\t\nb"),
+            doc!(0 5
+                p!(0 1 plain!(0 1))
+                empty!(1 4 empty_line!(1 2) empty_line!(2 4))
+                p!(4 5 plain!(4 5))
+            )
+        );
+        assert_eq!(
+            parse("a\n \t \nb"),
+            doc!(0 7
+                p!(0 1 plain!(0 1))
+                empty!(1 6 empty_line!(1 2) empty_line!(2 6))
+                p!(6 7 plain!(6 7))
+            )
+        );
+    }
+
     #[test]
     fn test_plaintext() {
         assert_eq!(parse("ABC"), doc!(0 3 p!(0 3 plain!(0 3))));
-        assert_eq!(parse("Hello,\nWorld!"), doc!(0 13 p!(0 13 plain!(0 13))));
+        assert_eq!(
+            parse("Hello,\nWorld!"),
+            doc!(0 13 p!(0 13 plain!(0 6) ws!(6 7) plain!(7 13)))
+        );
         // TODO: should doc end at 3?
         assert_eq!(parse("A \n"), doc!(0 2 p!(0 2 plain!(0 2))));
     }
 
+    #[test]
+    fn test_continuation_lines() {
+        // Continuation lines leave out the markers and indentation the
+        // parser skipped, so text nodes hold only content
+        assert_eq!(
+            parse("> a\n> b"),
+            doc!(0 7 bq!(0 7 p!(2 7 plain!(2 3) ws!(3 4) plain!(6 7))))
+        );
+        assert_eq!(
+            parse("* a\n  b"),
+            doc!(0 7 ul!(2 7 li!((0, 1) 2 7 p!(2 7 plain!(2 3) ws!(3 4) plain!(6 7)))))
+        );
+        assert_eq!(
+            parse("a\n   b"),
+            doc!(0 6 p!(0 6 plain!(0 1) ws!(1 2) plain!(5 6)))
+        );
+    }
+
+    #[test]
+    fn test_asterisk_run() {
+        // Without emphasis parsing an unpaired run stays one text node
+        assert_eq!(parse("****"), doc!(0 4 p!(0 4 plain!(0 4))));
+        assert_eq!(parse("**** text"), doc!(0 9 p!(0 9 plain!(0 9))));
+        assert_eq!(parse("foo*bar*baz"), doc!(0 11 p!(0 11 plain!(0 11))));
+    }
+
+    #[test]
+    fn test_paragraph_interrupt() {
+        // A paragraph ends before a line that opens another block
+        assert_eq!(
+            parse("text\n# heading"),
+            doc!(0 14
+                p!(0 4 plain!(0 4))
+                empty!(4 5 empty_line!(4 5))
+                h!(# 7 14 plain!(7 14))
+            )
+        );
+        assert_eq!(
+            parse("text\n> q"),
+            doc!(0 8
+                p!(0 4 plain!(0 4))
+                empty!(4 5 empty_line!(4 5))
+                bq!(5 8 p!(7 8 plain!(7 8)))
+            )
+        );
+        assert_eq!(
+            parse("text\n* item"),
+            doc!(0 11
+                p!(0 4 plain!(0 4))
+                empty!(4 5 empty_line!(4 5))
+                ul!(7 11 li!((5, 6) 7 11 p!(7 11 plain!(7 11))))
+            )
+        );
+        // A `#` without whitespace after it doesn't open a heading, so the
+        // line continues the paragraph, in a list item too
+        assert_eq!(
+            parse("text\n#tag"),
+            doc!(0 9 p!(0 9 plain!(0 4) ws!(4 5) plain!(5 9)))
+        );
+        assert_eq!(
+            parse("- a\n#tag"),
+            doc!(0 8 ul!(2 8 li!((0, 1) 2 8 p!(2 8 plain!(2 3) ws!(3 4) plain!(4 8)))))
+        );
+    }
+
     #[test]
     fn test_heading() {
         assert_eq!(parse("# Hello"), doc!(0 7 h!(# 2 7 plain!(2 7))));
@@ -562,7 +751,7 @@ mod test {
             parse("# \n## A B C"),
             doc!(
                 0 11
-                h!(# 2 3)
+                h!(# 2 2)
                 empty!(2 3 empty_line!(2 3))
                 h!(## 6 11 plain!(6 11))
             )
@@ -571,7 +760,7 @@ mod test {
             parse("#\n#A"),
             doc!(
                 0 4
-                h!(# 1 2)
+                h!(# 1 1)
                 empty!(1 2 empty_line!(1 2))
                 // TODO: should we merge adjacent plaintext tokens?
                 p!(2 4 plain!(2 4))
@@ -581,13 +770,60 @@ mod test {
             parse("* \n# Heading\n\n"),
             doc!(
                 0 14
-                ul!(0 3 li!(2 3))
-                h!(# 5 12)
+                ul!(2 3 li!((0, 1) 2 3 empty!(2 3 empty_line!(2 3))))
+                h!(# 5 12 plain!(5 12))
                 empty!(12 14 empty_line!(12 13) empty_line!(13 14))
             )
         )
     }
 
+    #[test]
+    fn test_setext_underline_alone() {
+        // Setext headings aren't supported, but an underline with nothing
+        // to underline must stay a paragraph once they are
+        assert_eq!(parse("==="), doc!(0 3 p!(0 3 plain!(0 3))));
+        assert_eq!(
+            parse("A\n\n==="),
+            doc!(0 6
+                p!(0 1 plain!(0 1))
+                empty!(1 3 empty_line!(1 2) empty_line!(2 3))
+                p!(3 6 plain!(3 6))
+            )
+        );
+    }
+
+    #[test]
+    fn test_heading_whitespace() {
+        assert_eq!(parse("#   A   B   "), doc!(0 9 h!(# 4 9 plain!(4 9))));
+        assert_eq!(
+            parse("#   A   B   \nC"),
+            doc!(0 14
+                h!(# 4 9 plain!(4 9))
+                empty!(9 13 empty_line!(9 13))
+                p!(13 14 plain!(13 14))
+            )
+        );
+        assert_eq!(parse("# A\t"), doc!(0 3 h!(# 2 3 plain!(2 3))));
+    }
+
+    #[test]
+    fn test_heading_all_hashes() {
+        assert_eq!(parse("######"), doc!(0 6 Node::new(Kind::Heading(6), 6, 6)));
+        assert_eq!(
+            parse("######\nA"),
+            doc!(0 8
+                Node::new(Kind::Heading(6), 6, 6)
+                empty!(6 7 empty_line!(6 7))
+                p!(7 8 plain!(7 8))
+            )
+        );
+        assert_eq!(parse("#######"), doc!(0 7 p!(0 7 plain!(0 7))));
+        assert_eq!(
+            parse("#######\nA"),
+            doc!(0 9 p!(0 9 plain!(0 7) ws!(7 8) plain!(8 9)))
+        );
+    }
+
     #[test]
     fn test_block_quote() {
         // let result = parse(">\n\n");
@@ -602,7 +838,90 @@ mod test {
         // let result = parse(">\n\nABC");
         // let result = parse(">ABC\n>\n>TWO\n");
         // let result = parse("> A\n\nB");
-        dbg!(&result);
+        assert_eq!(
+            result.event_log(),
+            vec![
+                "enter document 0..22",
+                "enter block_quote 0..21",
+                "enter unordered_list(tight) 4..21",
+                "enter list_item(2..3) 4..21",
+                "enter paragraph 4..5",
+                "text 4..5",
+                "exit paragraph",
+                "enter empty 5..6",
+                "empty_line 5..6",
+                "exit empty",
+                "enter unordered_list(tight) 12..21",
+                "enter list_item(10..11) 12..14",
+                "enter paragraph 12..13",
+                "text 12..13",
+                "exit paragraph",
+                "exit list_item",
+                "enter list_item(18..19) 20..21",
+                "enter empty 20..21",
+                "empty_line 20..21",
+                "exit empty",
+                "exit list_item",
+                "exit unordered_list",
+                "exit list_item",
+                "exit unordered_list",
+                "exit block_quote",
+                "enter empty 21..22",
+                "empty_line 21..22",
+                "exit empty",
+                "exit document",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_block_quote_markers() {
+        assert_eq!(parse(">\ta"), doc!(0 3 bq!(0 3 p!(2 3 plain!(2 3)))));
+        assert_eq!(parse("   > a"), doc!(0 6 bq!(3 6 p!(5 6 plain!(5 6)))));
+        assert_eq!(
+            parse(">\ta\n>\tb"),
+            doc!(0 7 bq!(0 7 p!(2 7 plain!(2 3) ws!(3 4) plain!(6 7))))
+        );
+        assert_eq!(
+            parse(">\t* a\n>\t* b"),
+            doc!(0 11 bq!(0 11 ul!(4 11
+                li!((2, 3) 4 6 p!(4 5 plain!(4 5)))
+                li!((8, 9) 10 11 p!(10 11 plain!(10 11)))
+            )))
+        );
+    }
+
+    #[test]
+    fn test_right_caret_literal() {
+        assert_eq!(parse("a > b"), doc!(0 5 p!(0 5 plain!(0 5))));
+        assert_eq!(parse("> quote"), doc!(0 7 bq!(0 7 p!(2 7 plain!(2 7)))));
+        assert_eq!(
+            parse("a\n> b >"),
+            doc!(0 7
+                p!(0 1 plain!(0 1))
+                empty!(1 2 empty_line!(1 2))
+                bq!(2 7 p!(4 7 plain!(4 7)))
+            )
+        );
+    }
+
+    #[test]
+    fn test_block_priority() {
+        // The line's first opener decides the block; later markers belong to
+        // its content
+        assert_eq!(parse("# > a"), doc!(0 5 h!(# 2 5 plain!(2 5))));
+        assert_eq!(
+            parse("* # a"),
+            doc!(0 5 ul!(2 5 li!((0, 1) 2 5 h!(# 4 5 plain!(4 5)))))
+        );
+        // A bare marker opens an empty list item rather than a paragraph
+        assert_eq!(parse("1. "), doc!(0 2 ol!(2 2 li!((0, 2) 2 2))));
+    }
+
+    #[test]
+    fn test_hash_literal() {
+        assert_eq!(parse("a # b"), doc!(0 5 p!(0 5 plain!(0 5))));
+        assert_eq!(parse("# a"), doc!(0 3 h!(# 2 3 plain!(2 3))));
     }
 
     #[test]
@@ -635,11 +954,11 @@ mod test {
             doc!(
                 0 6
                 ul!(2 6
-                    li!(2 3
+                    li!((0, 1) 2 3
                         // TODO: is this really an empty line?
                         empty!(2 3 empty_line!(2 3))
                     )
-                    li!(5 6 p!(5 6 plain!(5 6)))
+                    li!((3, 4) 5 6 p!(5 6 plain!(5 6)))
                 )
             )
         );
@@ -648,9 +967,9 @@ mod test {
             doc!(
                 0 8
                 ul!(2 8
-                    li!(2 8
+                    li!((0, 1) 2 8
                         empty!(2 3 empty_line!(2 3))
-                        ul!(7 8 li!(7 8 p!(7 8 plain!(7 8))))
+                        ul!(7 8 li!((5, 6) 7 8 p!(7 8 plain!(7 8))))
                     )
                 )
             )
@@ -658,13 +977,111 @@ mod test {
         assert_eq!(
             parse("* A\n\n* B"),
             doc!(0 8 ul!(t 2 8
-                li!(2 5
+                li!((0, 1) 2 5
                     p!(2 3 plain!(2 3))
                     empty!(4 5 empty_line!(4 5)) // TODO: two empty lines?
                 )
-                li!(7 8 p!(7 8 plain!(7 8)))
+                li!((5, 6) 7 8 p!(7 8 plain!(7 8)))
+            ))
+        );
+    }
+
+    #[test]
+    fn test_list_tightness() {
+        // A blank line after the last item does not make the list loose
+        assert_eq!(
+            parse("- a\n- b\n\ntext"),
+            doc!(0 13
+                ul!(2 8
+                    li!((0, 1) 2 4 p!(2 3 plain!(2 3)))
+                    li!((4, 5) 6 8 p!(6 7 plain!(6 7)))
+                )
+                empty!(8 9 empty_line!(8 9))
+                p!(9 13 plain!(9 13))
+            )
+        );
+        assert_eq!(
+            parse("- a\n\n- b"),
+            doc!(0 8 ul!(t 2 8
+                li!((0, 1) 2 5
+                    p!(2 3 plain!(2 3))
+                    empty!(4 5 empty_line!(4 5))
+                )
+                li!((5, 6) 7 8 p!(7 8 plain!(7 8)))
+            ))
+        );
+        // Nor does a following list with a different marker type
+        assert_eq!(
+            parse("- a\n- b\n\n1. c"),
+            doc!(0 13
+                ul!(2 8
+                    li!((0, 1) 2 4 p!(2 3 plain!(2 3)))
+                    li!((4, 5) 6 8 p!(6 7 plain!(6 7)))
+                )
+                empty!(8 9 empty_line!(8 9))
+                ol!(12 13 li!((9, 11) 12 13 p!(12 13 plain!(12 13))))
+            )
+        );
+    }
+
+    #[test]
+    fn test_nested_list_tightness() {
+        // Each list gets its own looseness; tight inner lists stay tight
+        // inside a loose outer list
+        let result = parse("* a\n  * b\n  * c\n\n* d\n  * e\n  * f");
+        let outer = &result.children[0];
+        assert_eq!(outer.kind, Kind::UnorderedList(true));
+        for item in &outer.children {
+            let inner = item
+                .children
+                .iter()
+                .find(|n| n.kind.name() == "UnorderedList");
+            assert_eq!(inner.map(|n| &n.kind), Some(&Kind::UnorderedList(false)));
+        }
+    }
+
+    #[test]
+    fn test_bullet_whitespace() {
+        assert_eq!(parse("-x"), doc!(0 2 p!(0 2 plain!(0 2))));
+        assert_eq!(
+            parse("- x"),
+            doc!(0 3 ul!(2 3 li!((0, 1) 2 3 p!(2 3 plain!(2 3)))))
+        );
+        assert_eq!(parse("-"), doc!(0 1 ul!(1 1 li!((0, 1) 1 1))));
+        assert_eq!(
+            parse("- a\n-"),
+            doc!(0 5 ul!(2 5
+                li!((0, 1) 2 4 p!(2 3 plain!(2 3)))
+                li!((4, 5) 5 5)
             ))
         );
+        assert_eq!(parse("1."), doc!(0 2 ol!(2 2 li!((0, 2) 2 2))));
+        // An empty item can't interrupt a paragraph
+        assert_eq!(
+            parse("A\n-"),
+            doc!(0 3 p!(0 3 plain!(0 1) ws!(1 2) plain!(2 3)))
+        );
+    }
+
+    #[test]
+    fn test_list_marker() {
+        let source = "- a\n  1. b\n> * c";
+        let doc = parse(source);
+        let outer = &doc.children[0].children[0];
+        let inner = &outer.children[2].children[0];
+        let quoted = &doc.children[1].children[0].children[0];
+        assert_eq!(outer.kind, Kind::ListItem((0, 1)));
+        assert_eq!(inner.kind, Kind::ListItem((6, 8)));
+        assert_eq!(quoted.kind, Kind::ListItem((13, 14)));
+
+        // Rewriting the marker bytes changes only the marker
+        if let Kind::ListItem((start, end)) = inner.kind {
+            assert_eq!(&source[start..end], "1.");
+            let edited = [&source[..start], "2)", &source[end..]].concat();
+            let inner = &parse(&edited).children[0].children[0].children[2].children[0];
+            assert_eq!(inner.kind, Kind::ListItem((6, 8)));
+            assert_eq!(inner.span, (9, 11));
+        }
     }
 
     #[test]
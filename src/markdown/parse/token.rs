@@ -1,4 +1,5 @@
-const WHITESPACE_CHARS: [&str; 2] = [" ", "\t"];
+use std::ops::Range;
+
 const NUMBER_CHARS: [&str; 10] = ["0", "1", "2", "3", "4", "5", "6", "7", "8", "9"];
 
 pub type Span = (usize, usize);
@@ -32,6 +33,21 @@ impl Token {
             Token::Newline(s) => *s,
         }
     }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Token::RightCaret(..) => "RightCaret",
+            Token::Hash(..) => "Hash",
+            Token::Dash(..) => "Dash",
+            Token::Asterisk(..) => "Asterisk",
+            Token::Plus(..) => "Plus",
+            Token::NumDot(..) => "NumDot",
+            Token::NumParen(..) => "NumParen",
+            Token::Plaintext(..) => "Plaintext",
+            Token::Whitespace(..) => "Whitespace",
+            Token::Newline(..) => "Newline",
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
@@ -47,11 +63,82 @@ enum TokenizerState {
 pub struct Tokenizer<'a> {
     start: usize,
     source: &'a str,
+    ascii: bool,
 }
 
 impl<'a> Tokenizer<'a> {
+    /// Tokenize `source` from byte `start`.
+    ///
+    /// # Panics
+    ///
+    /// If `start` is past the end of `source` or inside a multibyte char.
     pub fn new(start: usize, source: &'a str) -> Self {
-        Tokenizer { start, source }
+        assert!(
+            source.is_char_boundary(start),
+            "tokenizer start {} is not on a char boundary",
+            start
+        );
+        let ascii = source.is_ascii();
+        Tokenizer {
+            start,
+            source,
+            ascii,
+        }
+    }
+
+    /// Yield each token with the source text it covers.
+    pub fn with_text(self) -> WithText<'a> {
+        WithText { tokens: self }
+    }
+
+    /// The character at byte `p`. Every char is one byte in an ASCII source,
+    /// otherwise multibyte chars are kept whole so tokens end on boundaries.
+    fn char_at(&self, p: usize) -> Option<&'a str> {
+        if self.ascii {
+            return self.source.get(p..p + 1);
+        }
+        let c = self.source.get(p..)?.chars().next()?;
+        self.source.get(p..p + c.len_utf8())
+    }
+
+    /// Yield each token with the 1-based line it starts on.
+    pub fn with_line_numbers(self) -> LineNumbers<'a> {
+        LineNumbers {
+            tokens: self,
+            line: 1,
+        }
+    }
+}
+
+pub struct WithText<'a> {
+    tokens: Tokenizer<'a>,
+}
+
+impl<'a> Iterator for WithText<'a> {
+    type Item = (Token, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let token = self.tokens.next()?;
+        let (start, end) = token.span();
+        Some((token, &self.tokens.source[start..end]))
+    }
+}
+
+pub struct LineNumbers<'a> {
+    tokens: Tokenizer<'a>,
+    line: usize,
+}
+
+impl<'a> Iterator for LineNumbers<'a> {
+    type Item = (Token, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let token = self.tokens.next()?;
+        let line = self.line;
+        if let Token::Newline(..) = token {
+            self.line += 1;
+        }
+        Some((token, line))
     }
 }
 
@@ -64,25 +151,22 @@ impl<'a> Iterator for Tokenizer<'a> {
         let mut result = None;
 
         while state != TokenizerState::Done {
-            let (new_state, new_p) = match (state, self.source.get(p..p + 1)) {
+            let (new_state, new_p) = match (state, self.char_at(p)) {
                 // Whitespace
-                (TokenizerState::Whitespace, Some(c)) if WHITESPACE_CHARS.contains(&c) => {
-                    (TokenizerState::Whitespace, p + 1)
-                }
                 (TokenizerState::Whitespace, _) => {
-                    result = Some(Token::Whitespace((self.start, p)));
-                    (TokenizerState::Done, p)
+                    let run = whitespace_run(&self.source.as_bytes()[p..]);
+                    result = Some(Token::Whitespace((self.start, p + run)));
+                    (TokenizerState::Done, p + run)
                 }
-                // Plaintext
-                (TokenizerState::Plaintext, Some(c)) if WHITESPACE_CHARS.contains(&c) => {
+                // Plaintext. `p` is on a char boundary, so its byte is only
+                // one of these ASCII bytes if the char is
+                (TokenizerState::Plaintext, Some(_))
+                    if matches!(self.source.as_bytes()[p], b' ' | b'\t' | b'\n' | b'*') =>
+                {
                     result = Some(Token::Plaintext((self.start, p)));
                     (TokenizerState::Done, p)
                 }
-                (TokenizerState::Plaintext, Some("\n")) => {
-                    result = Some(Token::Plaintext((self.start, p)));
-                    (TokenizerState::Done, p)
-                }
-                (TokenizerState::Plaintext, Some(_)) => (TokenizerState::Plaintext, p + 1),
+                (TokenizerState::Plaintext, Some(c)) => (TokenizerState::Plaintext, p + c.len()),
                 (TokenizerState::Plaintext, None) => {
                     result = Some(Token::Plaintext((self.start, p)));
                     (TokenizerState::Done, p)
@@ -122,7 +206,9 @@ impl<'a> Iterator for Tokenizer<'a> {
                     (TokenizerState::Done, p + 1)
                 }
                 // Unset
-                (TokenizerState::Unset, Some(c)) if WHITESPACE_CHARS.contains(&c) => {
+                (TokenizerState::Unset, Some(_))
+                    if matches!(self.source.as_bytes()[p], b' ' | b'\t') =>
+                {
                     (TokenizerState::Whitespace, p + 1)
                 }
                 (TokenizerState::Unset, Some(c)) if NUMBER_CHARS.contains(&c) => {
@@ -140,7 +226,7 @@ impl<'a> Iterator for Tokenizer<'a> {
                     result = Some(Token::Hash((self.start, p + 1)));
                     (TokenizerState::Hash, p + 1)
                 }
-                (TokenizerState::Unset, Some(_)) => (TokenizerState::Plaintext, p + 1),
+                (TokenizerState::Unset, Some(c)) => (TokenizerState::Plaintext, p + c.len()),
                 // Done
                 _ => (TokenizerState::Done, p),
             };
@@ -153,9 +239,99 @@ impl<'a> Iterator for Tokenizer<'a> {
     }
 }
 
+/// Length of the run of spaces and tabs at the start of `bytes`. Both are
+/// single bytes that never occur inside a multibyte char, so the run can be
+/// scanned as bytes whether or not the source is ASCII.
+fn whitespace_run(bytes: &[u8]) -> usize {
+    bytes
+        .iter()
+        .take_while(|b| matches!(b, b' ' | b'\t'))
+        .count()
+}
+
+/// A human-readable dump of the tokens in `source`, each followed by the
+/// text it covers, e.g. `Hash(0..3) "###" Whitespace(3..4) " "`.
+pub fn debug_tokens(source: &str) -> String {
+    Tokenizer::new(0, source)
+        .with_text()
+        .map(|(t, text)| {
+            let (start, end) = t.span();
+            format!("{}({}..{}) {:?}", t.name(), start, end, text)
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Byte range of the previous source that has to be re-tokenized after the
+/// `edit` range of it is replaced. The tokenizer keeps no state between
+/// tokens, so only tokens touching the edit can change. The range is never
+/// grown for multi-line constructs: fenced code is not tokenized or parsed
+/// yet, so an edit that opens a fence is treated like any other edit.
+pub fn invalidated_range(prev_tokens: &[Token], edit: Range<usize>) -> Range<usize> {
+    let start = prev_tokens
+        .iter()
+        .map(|t| t.span())
+        .find(|(_, end)| *end >= edit.start)
+        .map(|(start, _)| start.min(edit.start))
+        .unwrap_or(edit.start);
+    let end = prev_tokens
+        .iter()
+        .rev()
+        .map(|t| t.span())
+        .find(|(start, _)| *start <= edit.end)
+        .map(|(_, end)| end.max(edit.end))
+        .unwrap_or(edit.end);
+    start..end
+}
+
 #[cfg(test)]
 mod test {
+    extern crate test;
+
     use super::*;
+    use test::Bencher;
+
+    fn general(source: &str) -> Tokenizer<'_> {
+        Tokenizer {
+            start: 0,
+            source,
+            ascii: false,
+        }
+    }
+
+    const WHITESPACE_CHARS: [&str; 2] = [" ", "\t"];
+
+    // The whitespace scan as it was before `whitespace_run`: one char at a
+    // time, each compared against `WHITESPACE_CHARS`
+    fn whitespace_run_by_char(source: &str) -> usize {
+        let tokenizer = general(source);
+        let mut p = 0;
+        while let Some(c) = tokenizer.char_at(p) {
+            if !WHITESPACE_CHARS.contains(&c) {
+                break;
+            }
+            p += c.len();
+        }
+        p
+    }
+
+    fn indented_document() -> String {
+        format!("{}* item\t\t  \n", " ".repeat(64)).repeat(1000)
+    }
+
+    // Start of each whitespace run in `source`
+    fn whitespace_starts(source: &str) -> Vec<usize> {
+        Tokenizer::new(0, source)
+            .filter_map(|t| match t {
+                Token::Whitespace((start, _)) => Some(start),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn large_ascii_document() -> String {
+        "# Heading\n\n> Some *quoted* text, 12 lines\n\n1. One\n2. Two\n\t- three\n".repeat(1000)
+    }
 
     #[test]
     fn test_plaintext() {
@@ -208,6 +384,63 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_ol_start() {
+        // `start` indexes into `source`, so the spans are absolute
+        let source = format!("{}1. item\n2) item", "x".repeat(100));
+        let result = Tokenizer::new(100, &source).collect::<Vec<_>>();
+
+        assert_eq!(
+            result,
+            vec![
+                Token::NumDot((100, 102)),
+                Token::Whitespace((102, 103)),
+                Token::Plaintext((103, 107)),
+                Token::Newline((107, 108)),
+                Token::NumParen((108, 110)),
+                Token::Whitespace((110, 111)),
+                Token::Plaintext((111, 115)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_asterisk_mid_word() {
+        let tokenizer = Tokenizer::new(0, "foo*bar*baz 1*");
+        let result = tokenizer.into_iter().collect::<Vec<_>>();
+
+        assert_eq!(
+            result,
+            vec![
+                Token::Plaintext((0, 3)),
+                Token::Asterisk((3, 4)),
+                Token::Plaintext((4, 7)),
+                Token::Asterisk((7, 8)),
+                Token::Plaintext((8, 11)),
+                Token::Whitespace((11, 12)),
+                Token::Plaintext((12, 13)),
+                Token::Asterisk((13, 14)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_plaintext_start() {
+        let result = Tokenizer::new(5, "abcd hello").collect::<Vec<_>>();
+        assert_eq!(result, vec![Token::Plaintext((5, 10))]);
+
+        let result = Tokenizer::new(2, "abcd hello").collect::<Vec<_>>();
+        assert_eq!(
+            result,
+            vec![
+                Token::Plaintext((2, 4)),
+                Token::Whitespace((4, 5)),
+                Token::Plaintext((5, 10)),
+            ]
+        );
+        assert_eq!(Tokenizer::new(5, "hello").next(), None);
+    }
+
     #[test]
     fn test_numbers() {
         let tokenizer = Tokenizer::new(0, "Test 123 Test");
@@ -224,4 +457,171 @@ mod test {
             ]
         );
     }
+
+    #[test]
+    fn test_invalidated_range() {
+        let source = "Hello, World!\n## Heading";
+        let tokens = Tokenizer::new(0, source).collect::<Vec<_>>();
+
+        // Inside a word only that word is rescanned
+        assert_eq!(invalidated_range(&tokens, 9..10), 7..13);
+        // At a token boundary both neighbours may merge
+        assert_eq!(invalidated_range(&tokens, 6..6), 0..7);
+        // Spanning a newline covers both lines' touched tokens
+        assert_eq!(invalidated_range(&tokens, 12..15), 7..16);
+        // Appending at EOF
+        assert_eq!(invalidated_range(&tokens, 24..24), 17..24);
+        assert_eq!(invalidated_range(&[], 0..0), 0..0);
+    }
+
+    #[test]
+    fn test_debug_tokens() {
+        assert_eq!(
+            debug_tokens("### Header Text"),
+            "Hash(0..3) \"###\" Whitespace(3..4) \" \" Plaintext(4..10) \"Header\" \
+             Whitespace(10..11) \" \" Plaintext(11..15) \"Text\""
+        );
+        assert_eq!(
+            debug_tokens("> A\n"),
+            "RightCaret(0..1) \">\" Whitespace(1..2) \" \" Plaintext(2..3) \"A\" \
+             Newline(3..4) \"\\n\""
+        );
+    }
+
+    #[test]
+    fn test_line_numbers() {
+        let tokenizer = Tokenizer::new(0, "# A\n\n> B");
+        let result = tokenizer.with_line_numbers().collect::<Vec<_>>();
+
+        assert_eq!(
+            result,
+            vec![
+                (Token::Hash((0, 1)), 1),
+                (Token::Whitespace((1, 2)), 1),
+                (Token::Plaintext((2, 3)), 1),
+                (Token::Newline((3, 4)), 1),
+                (Token::Newline((4, 5)), 2),
+                (Token::RightCaret((5, 6)), 3),
+                (Token::Whitespace((6, 7)), 3),
+                (Token::Plaintext((7, 8)), 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_with_text() {
+        let tokenizer = Tokenizer::new(0, "### Header Text");
+        let result = tokenizer.with_text().collect::<Vec<_>>();
+
+        assert_eq!(
+            result,
+            vec![
+                (Token::Hash((0, 3)), "###"),
+                (Token::Whitespace((3, 4)), " "),
+                (Token::Plaintext((4, 10)), "Header"),
+                (Token::Whitespace((10, 11)), " "),
+                (Token::Plaintext((11, 15)), "Text"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_non_ascii() {
+        let source = "# Café ☕\nß";
+        let result = Tokenizer::new(0, source).collect::<Vec<_>>();
+
+        assert_eq!(
+            result,
+            vec![
+                Token::Hash((0, 1)),
+                Token::Whitespace((1, 2)),
+                Token::Plaintext((2, 7)),
+                Token::Whitespace((7, 8)),
+                Token::Plaintext((8, 11)),
+                Token::Newline((11, 12)),
+                Token::Plaintext((12, 14)),
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "tokenizer start 2 is not on a char boundary")]
+    fn test_start_inside_char() {
+        Tokenizer::new(2, "#é");
+    }
+
+    #[test]
+    fn test_whitespace_run() {
+        let result = Tokenizer::new(0, " \t é\t\t\n \t").collect::<Vec<_>>();
+
+        assert_eq!(
+            result,
+            vec![
+                Token::Whitespace((0, 3)),
+                Token::Plaintext((3, 5)),
+                Token::Whitespace((5, 7)),
+                Token::Newline((7, 8)),
+                Token::Whitespace((8, 10)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ascii_fast_path() {
+        let source = large_ascii_document();
+        assert!(Tokenizer::new(0, &source).ascii);
+        assert!(Tokenizer::new(0, &source).eq(general(&source)));
+    }
+
+    #[bench]
+    fn bench_ascii_fast_path(b: &mut Bencher) {
+        let source = large_ascii_document();
+        b.iter(|| Tokenizer::new(0, &source).count());
+    }
+
+    #[test]
+    fn test_whitespace_run_by_byte() {
+        for source in &[indented_document(), " \t é\t\t\n \t".into()] {
+            for p in whitespace_starts(source) {
+                let run = whitespace_run(&source.as_bytes()[p..]);
+                assert_eq!(run, whitespace_run_by_char(&source[p..]));
+            }
+        }
+    }
+
+    #[bench]
+    fn bench_whitespace(b: &mut Bencher) {
+        let source = indented_document();
+        b.iter(|| Tokenizer::new(0, &source).count());
+    }
+
+    #[bench]
+    fn bench_whitespace_by_byte(b: &mut Bencher) {
+        let source = indented_document();
+        let starts = whitespace_starts(&source);
+        b.iter(|| {
+            starts
+                .iter()
+                .map(|p| whitespace_run(&source.as_bytes()[*p..]))
+                .sum::<usize>()
+        });
+    }
+
+    #[bench]
+    fn bench_whitespace_by_char(b: &mut Bencher) {
+        let source = indented_document();
+        let starts = whitespace_starts(&source);
+        b.iter(|| {
+            starts
+                .iter()
+                .map(|p| whitespace_run_by_char(&source[*p..]))
+                .sum::<usize>()
+        });
+    }
+
+    #[bench]
+    fn bench_ascii_general_path(b: &mut Bencher) {
+        let source = large_ascii_document();
+        b.iter(|| general(&source).count());
+    }
 }
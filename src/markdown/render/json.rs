@@ -1,5 +1,3 @@
-use std::convert::Into;
-
 use serde::Serialize;
 
 use crate::markdown::{Kind, Node};
@@ -27,9 +25,9 @@ pub enum K {
     Whitespace,
 }
 
-impl Into<i64> for K {
-    fn into(self) -> i64 {
-        match self {
+impl From<K> for i64 {
+    fn from(kind: K) -> i64 {
+        match kind {
             K::Document => 1,
             K::BlockQuote => 2,
             K::Empty => 3,
@@ -67,7 +65,7 @@ impl N {
             Kind::Empty => render_container(K::Empty, source, node),
             Kind::UnorderedList(..) => render_container(K::UnorderedList, source, node),
             Kind::OrderedList(..) => render_container(K::OrderedList, source, node),
-            Kind::ListItem => render_container(K::ListItem, source, node),
+            Kind::ListItem(..) => render_container(K::ListItem, source, node),
             Kind::Heading(size) => render_heading(source, node, size),
             Kind::Paragraph => render_container(K::Paragraph, source, node),
             Kind::EmptyLine => render_inline(K::EmptyLine, source, node),
@@ -140,19 +138,37 @@ fn hash_n(kind: K, span: (usize, usize), children: &Option<Vec<N>>, text: &Optio
     let start = start as i64;
     let end = end as i64;
     let kind: i64 = kind.into();
-    start + 11 * end + 17 * hash + 31 * kind
+    start
+        .wrapping_add(end.wrapping_mul(11))
+        .wrapping_add(hash.wrapping_mul(17))
+        .wrapping_add(kind.wrapping_mul(31))
+}
+
+// `hash * 31 + x`, wrapping like the JS string hash it mirrors rather than
+// overflowing on long input.
+fn hash_step(hash: i64, x: i64) -> i64 {
+    hash.wrapping_shl(5).wrapping_sub(hash).wrapping_add(x)
 }
 
 fn hash_str(s: &str) -> i64 {
-    s.chars().into_iter().fold(0, |hash, c| {
-        let h = (hash << 5) - hash + (c as i64);
-        h | 0
-    })
+    s.chars().fold(0, |hash, c| hash_step(hash, c as i64))
 }
 
-fn hash_vec(v: &Vec<N>) -> i64 {
-    v.iter().fold(0, |hash, n| {
-        let h = (hash << 5) - hash + n.merkle;
-        h | 0
-    })
+fn hash_vec(v: &[N]) -> i64 {
+    v.iter().fold(0, |hash, n| hash_step(hash, n.merkle))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::markdown::parse;
+
+    #[test]
+    fn test_hash_long_input() {
+        // Overflowed and panicked in debug builds before hashing wrapped
+        let source = format!("# {}\n\n{}", "z".repeat(5000), "> * item\n".repeat(500));
+        let a = render(&source, parse(&source));
+        assert_eq!(a, render(&source, parse(&source)));
+        assert_ne!(hash_str("ab"), hash_str("ba"));
+    }
 }
@@ -0,0 +1,230 @@
+use serde_json::{json, Map, Value};
+
+use super::start_number;
+use crate::markdown::{Kind, Node};
+
+// Renders the tree as an mdast (https://github.com/syntax-tree/mdast) root.
+// Positions are taken from the parsed spans. Those exclude heading and list
+// markers, so headings, lists and list items are moved back to start at their
+// marker, like blockquotes already do.
+struct Renderer<'a> {
+    source: &'a str,
+    lines: Vec<usize>,
+}
+
+impl<'a> Renderer<'a> {
+    fn new(source: &'a str) -> Self {
+        let lines = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Renderer { source, lines }
+    }
+
+    fn point(&self, offset: usize) -> Value {
+        let line = self.lines.partition_point(|start| *start <= offset) - 1;
+        let column = self.source[self.lines[line]..offset].chars().count();
+        json!({ "line": line + 1, "column": column + 1, "offset": offset })
+    }
+
+    fn position(&self, (start, end): (usize, usize)) -> Value {
+        json!({ "start": self.point(start), "end": self.point(end) })
+    }
+
+    // `loose` is whether the parent, if a list, is loose; mdast marks the
+    // items of a loose list as spread.
+    fn node(&self, node: &Node, loose: bool) -> Option<Value> {
+        let mut v = Map::new();
+        let (mut start, end) = node.span;
+        let kind = match node.kind {
+            Kind::Document => "root",
+            Kind::BlockQuote => "blockquote",
+            Kind::Empty | Kind::EmptyLine => return None,
+            Kind::UnorderedList(loose) => {
+                v.insert("ordered".into(), json!(false));
+                v.insert("start".into(), Value::Null);
+                v.insert("spread".into(), json!(loose));
+                start = self.first_marker(node).unwrap_or(start);
+                "list"
+            }
+            Kind::OrderedList(loose) => {
+                v.insert("ordered".into(), json!(true));
+                v.insert("start".into(), json!(start_number(self.source, node)));
+                v.insert("spread".into(), json!(loose));
+                start = self.first_marker(node).unwrap_or(start);
+                "list"
+            }
+            Kind::ListItem((marker, _)) => {
+                start = marker;
+                v.insert("spread".into(), json!(loose));
+                v.insert("checked".into(), Value::Null);
+                "listItem"
+            }
+            Kind::Heading(depth) => {
+                let before = self.source[..start].trim_end_matches([' ', '\t']);
+                start = before.trim_end_matches('#').len();
+                v.insert("depth".into(), json!(depth));
+                "heading"
+            }
+            Kind::Paragraph => "paragraph",
+            Kind::Plaintext | Kind::Whitespace => {
+                v.insert("value".into(), json!(&self.source[start..end]));
+                "text"
+            }
+        };
+        v.insert("type".into(), json!(kind));
+        if !v.contains_key("value") {
+            let loose = matches!(
+                node.kind,
+                Kind::UnorderedList(true) | Kind::OrderedList(true)
+            );
+            let mut children = vec![];
+            for child in node.children.iter().filter_map(|c| self.node(c, loose)) {
+                merge_text(&mut children, child);
+            }
+            v.insert("children".into(), Value::Array(children));
+        }
+        v.insert("position".into(), self.position((start, end)));
+        Some(Value::Object(v))
+    }
+
+    fn first_marker(&self, list: &Node) -> Option<usize> {
+        match list.children.first().map(|n| &n.kind) {
+            Some(Kind::ListItem((start, _))) => Some(*start),
+            _ => None,
+        }
+    }
+}
+
+// A paragraph's lines and line breaks are separate nodes in the tree but make
+// one text node in mdast, positioned from the first line to the last.
+fn merge_text(children: &mut Vec<Value>, child: Value) {
+    if let Some(last) = children.last_mut() {
+        if last["type"] == "text" && child["type"] == "text" {
+            let value = format!(
+                "{}{}",
+                last["value"].as_str().unwrap(),
+                child["value"].as_str().unwrap()
+            );
+            last["value"] = json!(value);
+            last["position"]["end"] = child["position"]["end"].clone();
+            return;
+        }
+    }
+    children.push(child);
+}
+
+pub fn render(source: &str, node: Node) -> String {
+    let root = Renderer::new(source)
+        .node(&node, false)
+        .unwrap_or(Value::Null);
+    serde_json::to_string(&root).unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::markdown::parse;
+
+    fn pos(start: (usize, usize, usize), end: (usize, usize, usize)) -> Value {
+        json!({
+            "start": { "line": start.0, "column": start.1, "offset": start.2 },
+            "end": { "line": end.0, "column": end.1, "offset": end.2 },
+        })
+    }
+
+    #[test]
+    fn test_simple_document() {
+        let source = "# Hi\n\nSome text";
+        let result: Value = serde_json::from_str(&render(source, parse(source))).unwrap();
+        let expected = json!({
+            "type": "root",
+            "children": [
+                {
+                    "type": "heading",
+                    "depth": 1,
+                    "children": [
+                        { "type": "text", "value": "Hi", "position": pos((1, 3, 2), (1, 5, 4)) },
+                    ],
+                    "position": pos((1, 1, 0), (1, 5, 4)),
+                },
+                {
+                    "type": "paragraph",
+                    "children": [
+                        { "type": "text", "value": "Some text", "position": pos((3, 1, 6), (3, 10, 15)) },
+                    ],
+                    "position": pos((3, 1, 6), (3, 10, 15)),
+                },
+            ],
+            "position": pos((1, 1, 0), (3, 10, 15)),
+        });
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_hash_text() {
+        let result: Value = serde_json::from_str(&render("a # b", parse("a # b"))).unwrap();
+        assert_eq!(result["children"][0]["type"], "paragraph");
+        assert_eq!(result["children"][0]["children"][0]["value"], "a # b");
+    }
+
+    #[test]
+    fn test_lists() {
+        let source = "3. A\n4. B\n\n> * C";
+        let result: Value = serde_json::from_str(&render(source, parse(source))).unwrap();
+        let children = &result["children"];
+
+        assert_eq!(children[0]["type"], "list");
+        assert_eq!(children[0]["ordered"], true);
+        assert_eq!(children[0]["start"], 3);
+        assert_eq!(children[0]["spread"], false);
+        assert_eq!(children[0]["position"]["start"]["offset"], 0);
+        assert_eq!(children[0]["children"][1]["type"], "listItem");
+        assert_eq!(children[0]["children"][1]["spread"], false);
+        assert_eq!(children[0]["children"][1]["position"]["start"]["offset"], 5);
+        assert_eq!(
+            children[0]["children"][1]["children"][0]["type"],
+            "paragraph"
+        );
+
+        assert_eq!(children[1]["type"], "blockquote");
+        assert_eq!(children[1]["children"][0]["ordered"], false);
+        assert_eq!(children[1]["children"][0]["start"], Value::Null);
+        assert_eq!(children[1]["position"]["start"]["line"], 4);
+        assert_eq!(
+            children[1]["children"][0]["children"][0]["position"]["start"]["column"],
+            3
+        );
+    }
+
+    #[test]
+    fn test_multi_line_text() {
+        let source = "> a\n> b";
+        let result: Value = serde_json::from_str(&render(source, parse(source))).unwrap();
+        let paragraph = &result["children"][0]["children"][0];
+        assert_eq!(paragraph["children"].as_array().unwrap().len(), 1);
+        assert_eq!(paragraph["children"][0]["value"], "a\nb");
+        assert_eq!(
+            paragraph["children"][0]["position"],
+            pos((1, 3, 2), (2, 4, 7))
+        );
+    }
+
+    #[test]
+    fn test_marker_positions() {
+        let source = "> ## A\n\n* a\n\n* b";
+        let result: Value = serde_json::from_str(&render(source, parse(source))).unwrap();
+        let children = &result["children"];
+
+        let heading = &children[0]["children"][0];
+        assert_eq!(heading["type"], "heading");
+        assert_eq!(heading["position"]["start"]["offset"], 2);
+        assert_eq!(heading["children"][0]["position"]["start"]["offset"], 5);
+
+        let list = &children[1];
+        assert_eq!(list["spread"], true);
+        assert_eq!(list["position"]["start"]["offset"], 8);
+        assert_eq!(list["children"][0]["spread"], true);
+        assert_eq!(list["children"][1]["spread"], true);
+        assert_eq!(list["children"][1]["position"]["start"]["offset"], 13);
+    }
+}
@@ -1 +1,60 @@
 pub mod json;
+pub mod mdast;
+pub mod vdom;
+
+use crate::markdown::{Kind, Node};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    /// Node tree with merkle hashes, see `json`.
+    Json,
+    /// An mdast root, see `mdast`.
+    Mdast,
+    /// Elements for building DOM nodes, see `vdom`.
+    Vdom,
+}
+
+/// Render `node` with the renderer for `format`.
+pub fn render(source: &str, node: Node, format: OutputFormat) -> String {
+    match format {
+        OutputFormat::Json => json::render(source, node),
+        OutputFormat::Mdast => mdast::render(source, node),
+        OutputFormat::Vdom => vdom::render(source, node),
+    }
+}
+
+/// Number on the first item of an ordered `list`, read from its marker.
+pub(crate) fn start_number(source: &str, list: &Node) -> Option<u64> {
+    match list.children.first().map(|n| &n.kind) {
+        Some(Kind::ListItem((start, end))) => {
+            let marker = &source[*start..*end];
+            marker[..marker.len() - 1].parse().ok()
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::markdown::parse;
+    use serde_json::Value;
+
+    #[test]
+    fn test_render_formats() {
+        let source = "# Hi\n\ntext";
+        let render = |format| {
+            let output = render(source, parse(source), format);
+            serde_json::from_str::<Value>(&output).unwrap()
+        };
+
+        let json = render(OutputFormat::Json);
+        assert_eq!(json["children"][0]["kind"], "Heading1");
+        assert_eq!(json["children"][0]["children"][0]["text"], "Hi");
+        assert_eq!(
+            render(OutputFormat::Mdast)["children"][0]["type"],
+            "heading"
+        );
+        assert_eq!(render(OutputFormat::Vdom)["children"][1]["tag"], "p");
+    }
+}
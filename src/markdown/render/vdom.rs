@@ -0,0 +1,432 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use super::start_number;
+use crate::markdown::{Kind, Node};
+
+/// An HTML element or text node that JS can turn into DOM nodes directly,
+/// without going through `innerHTML`. Text serializes as a bare string.
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum VNode {
+    Element {
+        tag: &'static str,
+        attrs: BTreeMap<&'static str, String>,
+        children: Vec<VNode>,
+    },
+    Text(String),
+}
+
+impl VNode {
+    fn element(tag: &'static str, children: Vec<VNode>) -> Self {
+        VNode::Element {
+            tag,
+            attrs: BTreeMap::new(),
+            children,
+        }
+    }
+}
+
+/// How `to_vdom_with` renders text.
+#[derive(Debug, Default, Clone)]
+pub struct VdomConfig {
+    /// Keep entities like `&amp;` in text as written instead of decoding
+    /// them. By default numeric references and a few common named entities
+    /// (`&amp;` `&lt;` `&gt;` `&quot;` `&apos;` `&nbsp;` `&copy;` `&reg;`
+    /// `&trade;` `&hellip;` `&ndash;` `&mdash;` and the curly quotes) are
+    /// decoded. Other names, like `&eacute;`, are not in the table and stay
+    /// encoded, unlike CommonMark, which decodes every HTML5 entity.
+    /// Decoded text needs no re-escaping, since text nodes are never parsed
+    /// as HTML.
+    pub preserve_entities: bool,
+    /// Replace each tab in text with this many spaces. Tabs are kept by
+    /// default.
+    pub tab_width: Option<usize>,
+    /// Collapse each run of whitespace in text, line breaks included, to one
+    /// space, as browsers display it. Off by default.
+    pub collapse_whitespace: bool,
+    /// Element for the document root, e.g. `<article class="markdown-body">`.
+    /// By default the root is a bare `div`.
+    pub container: Option<Container>,
+}
+
+/// A root element for `VdomConfig::container`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Container {
+    pub tag: &'static str,
+    pub attrs: BTreeMap<&'static str, String>,
+}
+
+const HEADINGS: [&str; 6] = ["h1", "h2", "h3", "h4", "h5", "h6"];
+
+/// Convert the tree to elements, with the document as a bare `div`. Items of
+/// tight lists hold their paragraphs' content without the `p`, as in HTML.
+pub fn to_vdom(source: &str, node: &Node) -> VNode {
+    to_vdom_with(source, node, &VdomConfig::default())
+}
+
+/// `to_vdom` with the root and text rendered as `config` says.
+pub fn to_vdom_with(source: &str, node: &Node, config: &VdomConfig) -> VNode {
+    nodes(source, node, config, false).pop().unwrap()
+}
+
+/// The content of `node` without its own element, for rendering a snippet
+/// such as a table cell. A paragraph gives its text without the `p`, and the
+/// paragraphs of any other block are unwrapped as in a tight list item.
+pub fn inline_content(source: &str, node: &Node, config: &VdomConfig) -> Vec<VNode> {
+    children(source, node, config, true)
+}
+
+fn children(source: &str, node: &Node, config: &VdomConfig, tight: bool) -> Vec<VNode> {
+    // A paragraph's lines and line breaks make one text node, but separate
+    // paragraphs unwrapped into the same block stay separate
+    let inline = matches!(node.kind, Kind::Paragraph | Kind::Heading(..));
+    let mut children: Vec<VNode> = vec![];
+    for child in node
+        .children
+        .iter()
+        .flat_map(|c| nodes(source, c, config, tight))
+    {
+        match (children.last_mut(), child) {
+            (Some(VNode::Text(text)), VNode::Text(more)) if inline => {
+                if config.collapse_whitespace && text.ends_with(' ') {
+                    text.push_str(more.trim_start_matches(' '));
+                } else {
+                    text.push_str(&more);
+                }
+            }
+            (_, child) => children.push(child),
+        }
+    }
+    children
+}
+
+fn nodes(source: &str, node: &Node, config: &VdomConfig, tight: bool) -> Vec<VNode> {
+    let children = |tight| children(source, node, config, tight);
+    let element = match node.kind {
+        Kind::Document => match &config.container {
+            Some(container) => VNode::Element {
+                tag: container.tag,
+                attrs: container.attrs.clone(),
+                children: children(false),
+            },
+            None => VNode::element("div", children(false)),
+        },
+        Kind::BlockQuote => VNode::element("blockquote", children(false)),
+        Kind::Empty | Kind::EmptyLine => return vec![],
+        Kind::UnorderedList(loose) => VNode::element("ul", children(!loose)),
+        Kind::OrderedList(loose) => {
+            let mut attrs = BTreeMap::new();
+            if let Some(start) = start_number(source, node).filter(|n| *n != 1) {
+                attrs.insert("start", start.to_string());
+            }
+            VNode::Element {
+                tag: "ol",
+                attrs,
+                children: children(!loose),
+            }
+        }
+        Kind::ListItem(..) => VNode::element("li", children(tight)),
+        Kind::Heading(level) => VNode::element(HEADINGS[level.min(6) - 1], children(false)),
+        Kind::Paragraph if tight => return children(false),
+        Kind::Paragraph => VNode::element("p", children(false)),
+        Kind::Plaintext | Kind::Whitespace => {
+            let (start, end) = node.span;
+            VNode::Text(text(&source[start..end], config))
+        }
+    };
+    vec![element]
+}
+
+fn text(source: &str, config: &VdomConfig) -> String {
+    let text = match config.tab_width {
+        Some(width) => source.replace('\t', &" ".repeat(width)),
+        None => source.into(),
+    };
+    let text = if config.collapse_whitespace {
+        collapse_whitespace(&text)
+    } else {
+        text
+    };
+    if config.preserve_entities {
+        text
+    } else {
+        decode_entities(&text)
+    }
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    let mut collapsed = String::with_capacity(text.len());
+    for c in text.chars() {
+        if !c.is_ascii_whitespace() {
+            collapsed.push(c);
+        } else if !collapsed.ends_with(' ') {
+            collapsed.push(' ');
+        }
+    }
+    collapsed
+}
+
+// Replaces numeric character references and the named entities `entity`
+// knows with the characters they stand for. Anything else, like
+// `&unknown;`, is kept as written.
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(i) = rest.find('&') {
+        decoded.push_str(&rest[..i]);
+        rest = &rest[i..];
+        // No entity name is longer than 31 characters, so don't search the
+        // rest of the text for a `;`
+        let end = rest.bytes().take(33).position(|b| b == b';');
+        match end.and_then(|end| Some((end, entity(&rest[1..end])?))) {
+            Some((end, c)) => {
+                decoded.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+// The character for `&name;`. Only the most common named entities are
+// known, not the full HTML set.
+fn entity(name: &str) -> Option<char> {
+    if let Some(number) = name.strip_prefix('#') {
+        let (digits, radix, max_len) = match number.strip_prefix(|c| c == 'x' || c == 'X') {
+            Some(hex) => (hex, 16, 6),
+            None => (number, 10, 7),
+        };
+        if digits.is_empty() || digits.len() > max_len || !digits.chars().all(|c| c.is_digit(radix))
+        {
+            return None;
+        }
+        // Like CommonMark, NUL and invalid code points become U+FFFD
+        let n = u32::from_str_radix(digits, radix).ok()?;
+        return Some(
+            std::char::from_u32(n)
+                .filter(|_| n != 0)
+                .unwrap_or('\u{fffd}'),
+        );
+    }
+    let c = match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => '\u{a0}',
+        "copy" => '©',
+        "reg" => '®',
+        "trade" => '™',
+        "hellip" => '…',
+        "ndash" => '–',
+        "mdash" => '—',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        _ => return None,
+    };
+    Some(c)
+}
+
+pub fn render(source: &str, node: Node) -> String {
+    render_with(source, node, &VdomConfig::default())
+}
+
+/// `render` with the root and text rendered as `config` says.
+pub fn render_with(source: &str, node: Node, config: &VdomConfig) -> String {
+    serde_json::to_string(&to_vdom_with(source, &node, config)).unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::markdown::parse;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn test_heading_and_paragraph() {
+        let source = "# Hi\n\nSome text";
+        let result: Value = serde_json::from_str(&render(source, parse(source))).unwrap();
+        let expected = json!({
+            "tag": "div",
+            "attrs": {},
+            "children": [
+                { "tag": "h1", "attrs": {}, "children": ["Hi"] },
+                { "tag": "p", "attrs": {}, "children": ["Some text"] },
+            ],
+        });
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_lists() {
+        let source = "3. A\n4. B\n\n* C\n\n* D";
+        let doc = to_vdom(source, &parse(source));
+        let li = |children| VNode::element("li", children);
+        let text = |s: &str| VNode::Text(s.into());
+
+        let mut attrs = BTreeMap::new();
+        attrs.insert("start", "3".into());
+        let expected = VNode::element(
+            "div",
+            vec![
+                VNode::Element {
+                    tag: "ol",
+                    attrs,
+                    children: vec![li(vec![text("A")]), li(vec![text("B")])],
+                },
+                VNode::element(
+                    "ul",
+                    vec![
+                        li(vec![VNode::element("p", vec![text("C")])]),
+                        li(vec![VNode::element("p", vec![text("D")])]),
+                    ],
+                ),
+            ],
+        );
+        assert_eq!(doc, expected);
+    }
+
+    #[test]
+    fn test_multi_line_paragraphs() {
+        let source = "> a\n> b\n\n* c\n  d";
+        let doc = to_vdom(source, &parse(source));
+        let text = |s: &str| VNode::Text(s.into());
+        let expected = VNode::element(
+            "div",
+            vec![
+                VNode::element("blockquote", vec![VNode::element("p", vec![text("a\nb")])]),
+                VNode::element("ul", vec![VNode::element("li", vec![text("c\nd")])]),
+            ],
+        );
+        assert_eq!(doc, expected);
+    }
+
+    #[test]
+    fn test_container() {
+        let source = "text";
+        let mut attrs = BTreeMap::new();
+        attrs.insert("class", "markdown-body".into());
+        let config = VdomConfig {
+            container: Some(Container {
+                tag: "article",
+                attrs: attrs.clone(),
+            }),
+            ..VdomConfig::default()
+        };
+        let p = || VNode::element("p", vec![VNode::Text("text".into())]);
+
+        assert_eq!(
+            to_vdom_with(source, &parse(source), &config),
+            VNode::Element {
+                tag: "article",
+                attrs,
+                children: vec![p()],
+            }
+        );
+        assert_eq!(
+            to_vdom(source, &parse(source)),
+            VNode::element("div", vec![p()])
+        );
+    }
+
+    #[test]
+    fn test_inline_content() {
+        let source = "Some text\n\n> a\n>\n> b";
+        let doc = parse(source);
+        let config = VdomConfig::default();
+        let text = |s: &str| VNode::Text(s.into());
+
+        let paragraph = &doc.children[0];
+        assert_eq!(
+            to_vdom_with(source, paragraph, &config),
+            VNode::element("p", vec![text("Some text")])
+        );
+        assert_eq!(
+            inline_content(source, paragraph, &config),
+            vec![text("Some text")]
+        );
+        // Within another block each paragraph is unwrapped as in a tight item
+        assert_eq!(
+            inline_content(source, &doc.children[2], &config),
+            vec![text("a"), text("b")]
+        );
+    }
+
+    #[test]
+    fn test_entities() {
+        let source = "&amp; &unknown; &#35;&#X41;&#0; AT&T &lt;b&gt &copy; &eacute;";
+        let text = |config: VdomConfig| {
+            let output = render_with(source, parse(source), &config);
+            let result: Value = serde_json::from_str(&output).unwrap();
+            result["children"][0]["children"][0].clone()
+        };
+
+        assert_eq!(
+            text(VdomConfig::default()),
+            // &eacute; is a real entity but outside the decoded subset
+            "& &unknown; #A\u{fffd} AT&T <b&gt © &eacute;"
+        );
+        let preserve = VdomConfig {
+            preserve_entities: true,
+            ..VdomConfig::default()
+        };
+        assert_eq!(text(preserve), source);
+    }
+
+    #[test]
+    fn test_collapse_whitespace() {
+        let source = "a    b\t c   \n  d\n\n> e\n>   f";
+        let collapse = VdomConfig {
+            collapse_whitespace: true,
+            ..VdomConfig::default()
+        };
+        let text = |s: &str| VNode::Text(s.into());
+        let p = |s| VNode::element("p", vec![text(s)]);
+
+        assert_eq!(
+            to_vdom_with(source, &parse(source), &collapse),
+            VNode::element(
+                "div",
+                vec![p("a b c d"), VNode::element("blockquote", vec![p("e f")])]
+            )
+        );
+        assert_eq!(
+            to_vdom(source, &parse(source)),
+            VNode::element(
+                "div",
+                vec![
+                    p("a    b\t c   \nd"),
+                    VNode::element("blockquote", vec![p("e\nf")])
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn test_tabs() {
+        let source = "a\tb &#9;";
+        let text = |config: VdomConfig| {
+            let output = render_with(source, parse(source), &config);
+            let result: Value = serde_json::from_str(&output).unwrap();
+            result["children"][0]["children"][0].clone()
+        };
+
+        assert_eq!(text(VdomConfig::default()), "a\tb \t");
+        let expand = VdomConfig {
+            tab_width: Some(4),
+            ..VdomConfig::default()
+        };
+        // Only literal tabs are expanded, not a tab written as a reference
+        assert_eq!(text(expand), "a    b \t");
+    }
+}
@@ -0,0 +1,106 @@
+use crate::markdown::{parse, Node};
+
+/// Which anomalies `try_parse` should treat as errors. Everything is off by
+/// default, in which case `try_parse` only fails when `parse` would.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StrictConfig {
+    /// A heading more than one level deeper than the heading before it.
+    pub heading_skips: bool,
+    /// More than one H1, nested ones included.
+    pub single_h1: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ParseError {
+    /// A heading more than one level deeper than the previous heading.
+    HeadingSkip {
+        offset: usize,
+        from: usize,
+        to: usize,
+    },
+    /// An H1 after the first.
+    ExtraH1 { offset: usize },
+}
+
+pub fn try_parse<S: AsRef<str>>(source: S, strict: StrictConfig) -> Result<Node, Vec<ParseError>> {
+    let doc = parse(source);
+    let mut errors = vec![];
+
+    if strict.heading_skips {
+        let skips = doc.level_skips().into_iter();
+        errors.extend(skips.map(|(from, to, node)| ParseError::HeadingSkip {
+            offset: node.span.0,
+            from,
+            to,
+        }));
+    }
+
+    if strict.single_h1 && doc.h1_count() > 1 {
+        let h1s = doc.h1s();
+        errors.extend(
+            h1s.skip(1)
+                .map(|n| ParseError::ExtraH1 { offset: n.span.0 }),
+        );
+    }
+
+    if errors.is_empty() {
+        Ok(doc)
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_heading_skips() {
+        let source = "# A\n\n### B\n\n## C\n\n#### D";
+        let strict = StrictConfig {
+            heading_skips: true,
+            ..StrictConfig::default()
+        };
+        assert_eq!(
+            try_parse(source, strict),
+            Err(vec![
+                ParseError::HeadingSkip {
+                    offset: 9,
+                    from: 1,
+                    to: 3
+                },
+                ParseError::HeadingSkip {
+                    offset: 23,
+                    from: 2,
+                    to: 4
+                },
+            ])
+        );
+        assert_eq!(
+            try_parse(source, StrictConfig::default()),
+            Ok(parse(source))
+        );
+        assert!(try_parse("## A\n\n### B\n\n# C\n\n## D", strict).is_ok());
+    }
+
+    #[test]
+    fn test_single_h1() {
+        let source = "# A\n\n## B\n\n# C";
+        let strict = StrictConfig {
+            single_h1: true,
+            ..StrictConfig::default()
+        };
+        assert_eq!(
+            try_parse(source, strict),
+            Err(vec![ParseError::ExtraH1 { offset: 13 }])
+        );
+        assert!(try_parse(source, StrictConfig::default()).is_ok());
+        assert!(try_parse("# A", strict).is_ok());
+
+        // Nested H1s count, as nested headings do for heading_skips
+        assert_eq!(
+            try_parse("# A\n\n> # quoted", strict),
+            Err(vec![ParseError::ExtraH1 { offset: 9 }])
+        );
+    }
+}
@@ -0,0 +1,975 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::markdown::{Kind, Node};
+
+/// A top-level heading and the source it governs, from the start of the
+/// heading line up to the next heading of the same or a higher level.
+#[derive(Debug, PartialEq)]
+pub struct Section {
+    pub level: usize,
+    pub title: String,
+    pub range: Range<usize>,
+}
+
+/// A block in a `block_outline`, with its 1-based line range (end
+/// exclusive) and the indentation of its first line.
+#[derive(Debug, PartialEq)]
+pub struct BlockToken {
+    pub kind: Kind,
+    pub lines: Range<usize>,
+    pub indent: usize,
+}
+
+/// Pre-order iterator over the nodes below a node, excluding the node itself.
+pub struct Descendants<'a> {
+    stack: Vec<&'a Node>,
+}
+
+impl<'a> Iterator for Descendants<'a> {
+    type Item = &'a Node;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        self.stack.extend(node.children.iter().rev());
+        Some(node)
+    }
+}
+
+impl Node {
+    pub fn descendants(&self) -> Descendants<'_> {
+        Descendants {
+            stack: self.children.iter().rev().collect(),
+        }
+    }
+
+    /// Keep at most `max_nodes` nodes, including `self`, dropping the rest in
+    /// pre-order. Returns whether anything was dropped. Every node that lost
+    /// descendants ends where its last kept child ends.
+    pub fn truncate(&mut self, max_nodes: usize) -> bool {
+        let mut budget = max_nodes.saturating_sub(1);
+        self.truncate_children(&mut budget)
+    }
+
+    fn truncate_children(&mut self, budget: &mut usize) -> bool {
+        let mut truncated = false;
+        let mut keep = 0;
+        for child in self.children.iter_mut() {
+            if *budget == 0 {
+                truncated = true;
+                break;
+            }
+            *budget -= 1;
+            keep += 1;
+            truncated |= child.truncate_children(budget);
+        }
+        self.children.truncate(keep);
+        if truncated {
+            // Nothing past the last kept child is left in the container
+            self.span.1 = self.children.last().map_or(self.span.0, |c| c.span.1);
+        }
+        truncated
+    }
+
+    /// Number of descendants of each kind, keyed by `Kind::name`.
+    pub fn kind_histogram(&self) -> HashMap<&'static str, usize> {
+        let mut counts = HashMap::new();
+        for n in self.descendants() {
+            *counts.entry(n.kind.name()).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    /// Find the parent of `child` by searching down from `self`. Nodes are
+    /// compared by address, so `child` must be borrowed from this tree.
+    pub fn parent(&self, child: &Node) -> Option<&Node> {
+        for c in self.children.iter() {
+            if std::ptr::eq(c, child) {
+                return Some(self);
+            }
+            if let Some(p) = c.parent(child) {
+                return Some(p);
+            }
+        }
+        None
+    }
+
+    /// Compare two trees by kind and shape. With `ignore_spans` the node and
+    /// list marker spans are not compared, only the structure.
+    pub fn structural_eq(&self, other: &Node, ignore_spans: bool) -> bool {
+        let same_kind = match (&self.kind, &other.kind) {
+            (Kind::ListItem(..), Kind::ListItem(..)) if ignore_spans => true,
+            (a, b) => a == b,
+        };
+        same_kind
+            && (ignore_spans || self.span == other.span)
+            && self.children.len() == other.children.len()
+            && self
+                .children
+                .iter()
+                .zip(other.children.iter())
+                .all(|(a, b)| a.structural_eq(b, ignore_spans))
+    }
+
+    /// Drop paragraphs with no text, list items holding only blank lines,
+    /// and lists left with no items, e.g. after a transform has removed
+    /// their content.
+    pub fn remove_empty(&mut self) {
+        for child in &mut self.children {
+            child.remove_empty();
+        }
+        self.children.retain(|c| !c.is_empty_block());
+    }
+
+    fn is_empty_block(&self) -> bool {
+        match self.kind {
+            Kind::Paragraph => !self.descendants().any(|n| n.kind == Kind::Plaintext),
+            Kind::ListItem(..) => self.children.iter().all(|n| n.kind == Kind::Empty),
+            Kind::UnorderedList(..) | Kind::OrderedList(..) => self.children.is_empty(),
+            _ => false,
+        }
+    }
+
+    /// Splice the blocks of another document onto the end of this one.
+    /// `source_offset` is where the other document's source starts in the
+    /// combined source, usually the length of this document's source.
+    pub fn append(&mut self, other: Node, source_offset: usize) {
+        for mut child in other.children {
+            child.shift(source_offset);
+            self.children.push(child);
+        }
+        let end = self.children.last().map(|n| n.span.1).unwrap_or(0);
+        self.span.1 = self.span.1.max(end);
+    }
+
+    fn shift(&mut self, offset: usize) {
+        let (start, end) = self.span;
+        self.span = (start + offset, end + offset);
+        if let Kind::ListItem((start, end)) = self.kind {
+            self.kind = Kind::ListItem((start + offset, end + offset));
+        }
+        for child in self.children.iter_mut() {
+            child.shift(offset);
+        }
+    }
+
+    /// Byte ranges of the top-level blocks that together tile `source`. Each
+    /// range runs through the end of its block's last line, so unchanged
+    /// blocks can be copied out verbatim; blank lines fall to the `Empty`
+    /// blocks between.
+    pub fn block_sources(&self, source: &str) -> Vec<Range<usize>> {
+        let mut start = 0;
+        let mut ranges = vec![];
+        for (i, n) in self.children.iter().enumerate() {
+            let end = if i + 1 == self.children.len() {
+                source.len()
+            } else if n.span.1 == 0 || source[..n.span.1].ends_with('\n') {
+                n.span.1
+            } else {
+                source[n.span.1..]
+                    .find('\n')
+                    .map_or(source.len(), |i| n.span.1 + i + 1)
+            };
+            let end = end.max(start);
+            ranges.push(start..end);
+            start = end;
+        }
+        ranges
+    }
+
+    /// Every block below `self` in document order, without inline content
+    /// or blank lines.
+    pub fn block_outline(&self, source: &str) -> Vec<BlockToken> {
+        let lines = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect::<Vec<_>>();
+        let line_of = |offset: usize| lines.partition_point(|start| *start <= offset);
+
+        self.descendants()
+            .filter(|n| {
+                !matches!(
+                    n.kind,
+                    Kind::Empty | Kind::EmptyLine | Kind::Plaintext | Kind::Whitespace
+                )
+            })
+            .map(|n| {
+                let (start, end) = n.span;
+                let first = line_of(start);
+                let text = &source[lines[first - 1]..];
+                BlockToken {
+                    kind: n.kind,
+                    lines: first..line_of(end.max(start + 1) - 1) + 1,
+                    indent: text.len() - text.trim_start_matches(&[' ', '\t'][..]).len(),
+                }
+            })
+            .collect()
+    }
+
+    /// Deepest heading level anywhere below `self`.
+    pub fn max_heading_level(&self) -> Option<usize> {
+        self.headings().map(|(level, _)| level).max()
+    }
+
+    /// Number of H1 headings, including those nested in block quotes and
+    /// lists, as `heading_level_skips` counts them.
+    pub fn h1_count(&self) -> usize {
+        self.h1s().count()
+    }
+
+    /// H1 headings below `self` in document order.
+    pub(crate) fn h1s(&self) -> impl Iterator<Item = &Node> {
+        self.headings()
+            .filter(|(level, _)| *level == 1)
+            .map(|(_, n)| n)
+    }
+
+    /// `(from_level, to_level)` for each heading more than one level deeper
+    /// than the heading before it, in document order.
+    pub fn heading_level_skips(&self) -> Vec<(usize, usize)> {
+        self.level_skips()
+            .into_iter()
+            .map(|(from, to, _)| (from, to))
+            .collect()
+    }
+
+    pub(crate) fn level_skips(&self) -> Vec<(usize, usize, &Node)> {
+        let mut prev = None;
+        let mut skips = vec![];
+        for (level, node) in self.headings() {
+            match prev {
+                Some(from) if level > from + 1 => skips.push((from, level, node)),
+                _ => {}
+            }
+            prev = Some(level);
+        }
+        skips
+    }
+
+    fn headings(&self) -> impl Iterator<Item = (usize, &Node)> {
+        self.descendants().filter_map(|n| match n.kind {
+            Kind::Heading(level) => Some((level, n)),
+            _ => None,
+        })
+    }
+
+    /// Top-level headings in document order, each with its section range.
+    pub fn sections(&self, source: &str) -> Vec<Section> {
+        let headings = self
+            .children
+            .iter()
+            .filter_map(|n| match n.kind {
+                Kind::Heading(level) => Some((level, n.span)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        headings
+            .iter()
+            .enumerate()
+            .map(|(i, (level, (start, end)))| {
+                let next = headings[i + 1..]
+                    .iter()
+                    .find(|(l, _)| l <= level)
+                    .map_or(source.len(), |(_, (start, _))| line_start(source, *start));
+                Section {
+                    level: *level,
+                    title: source[*start..*end].into(),
+                    range: line_start(source, *start)..next,
+                }
+            })
+            .collect()
+    }
+
+    /// Text of the blocks under the top-level `heading`, up to the next
+    /// heading of the same or a higher level, one line per line of text.
+    /// `heading` is compared by address, so it must be borrowed from `self`.
+    pub fn section_text(&self, heading: &Node, source: &str) -> Option<String> {
+        let i = self
+            .children
+            .iter()
+            .position(|n| std::ptr::eq(n, heading))?;
+        let level = match heading.kind {
+            Kind::Heading(level) => level,
+            _ => return None,
+        };
+        let text = self.children[i + 1..]
+            .iter()
+            .take_while(|n| !matches!(n.kind, Kind::Heading(l) if l <= level))
+            .flat_map(|n| std::iter::once(n).chain(n.descendants()))
+            .filter(|n| n.kind == Kind::Plaintext)
+            .map(|n| &source[n.span.0..n.span.1])
+            .collect::<Vec<_>>();
+        Some(text.join("\n"))
+    }
+
+    /// Titles of the top-level headings enclosing `offset`, outermost first.
+    pub fn section_path(&self, source: &str, offset: usize) -> Vec<String> {
+        let mut path: Vec<(usize, &str)> = vec![];
+        // A heading's span starts after its `#` run, but the section starts
+        // at the line, as in `sections`
+        let started = |n: &&Node| line_start(source, n.span.0) <= offset;
+        for n in self.children.iter().take_while(started) {
+            if let Kind::Heading(level) = n.kind {
+                let (start, end) = n.span;
+                path.retain(|(l, _)| *l < level);
+                path.push((level, &source[start..end]));
+            }
+        }
+        path.into_iter().map(|(_, title)| title.into()).collect()
+    }
+
+    /// Remove the first top-level H1 from the tree and return its text, so a
+    /// template can show it as the title instead of in the body.
+    pub fn take_title(&mut self, source: &str) -> Option<String> {
+        let i = self
+            .children
+            .iter()
+            .position(|n| n.kind == Kind::Heading(1))?;
+        let (start, end) = self.children.remove(i).span;
+        Some(source[start..end].into())
+    }
+
+    /// Text of the first top-level paragraph for a meta description, with
+    /// line breaks folded to spaces. Longer text is cut at the last word
+    /// boundary that leaves room for an ellipsis within `max_chars`. A budget
+    /// of 0 has no room for either, so it gives `None`.
+    pub fn description(&self, source: &str, max_chars: usize) -> Option<String> {
+        if max_chars == 0 {
+            return None;
+        }
+        let p = self.children.iter().find(|n| n.kind == Kind::Paragraph)?;
+        let (start, end) = p.span;
+        let text = source[start..end]
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        if text.chars().count() <= max_chars {
+            return Some(text);
+        }
+
+        let limit = max_chars - 1;
+        let cut = text
+            .char_indices()
+            .nth(limit)
+            .map_or(text.len(), |(i, _)| i);
+        let cut = if text[cut..].starts_with(' ') {
+            cut
+        } else {
+            text[..cut].rfind(' ').unwrap_or(cut)
+        };
+        Some(format!("{}…", text[..cut].trim_end()))
+    }
+
+    /// Source spans of `query` in the document's text, in order. Only text
+    /// nodes are searched, one line at a time, so block markers such as `#`
+    /// or a continuation line's `>` never match, and neither does a query
+    /// spanning a line break.
+    pub fn search(&self, query: &str, source: &str) -> Vec<(usize, usize)> {
+        if query.is_empty() {
+            return vec![];
+        }
+        self.descendants()
+            .filter(|n| n.kind == Kind::Plaintext)
+            .flat_map(|n| {
+                let (start, end) = n.span;
+                source[start..end]
+                    .match_indices(query)
+                    .map(move |(i, m)| (start + i, start + i + m.len()))
+            })
+            .collect()
+    }
+
+    /// The tree as one line per event, e.g. `enter heading(2) 2..8`,
+    /// `text 2..8`, `exit heading`, for compact snapshot assertions. Leaf
+    /// nodes are a single event.
+    pub fn event_log(&self) -> Vec<String> {
+        let mut log = vec![];
+        self.log_events(&mut log);
+        log
+    }
+
+    fn log_events(&self, log: &mut Vec<String>) {
+        let density = |loose| if loose { "loose" } else { "tight" };
+        let (name, args) = match self.kind {
+            Kind::Document => ("document", None),
+            Kind::BlockQuote => ("block_quote", None),
+            Kind::Empty => ("empty", None),
+            Kind::UnorderedList(loose) => ("unordered_list", Some(density(loose).into())),
+            Kind::OrderedList(loose) => ("ordered_list", Some(density(loose).into())),
+            Kind::ListItem((a, b)) => ("list_item", Some(format!("{}..{}", a, b))),
+            Kind::Heading(level) => ("heading", Some(level.to_string())),
+            Kind::Paragraph => ("paragraph", None),
+            Kind::EmptyLine => ("empty_line", None),
+            Kind::Plaintext => ("text", None),
+            Kind::Whitespace => ("whitespace", None),
+        };
+        let (start, end) = self.span;
+        let label = match args {
+            Some(args) => format!("{}({}) {}..{}", name, args, start, end),
+            None => format!("{} {}..{}", name, start, end),
+        };
+
+        match self.kind {
+            Kind::EmptyLine | Kind::Plaintext | Kind::Whitespace => log.push(label),
+            _ => {
+                log.push(format!("enter {}", label));
+                for child in &self.children {
+                    child.log_events(log);
+                }
+                log.push(format!("exit {}", name));
+            }
+        }
+    }
+
+    /// Child indices leading from `self` to `node`, compared by address.
+    /// The root's path is empty.
+    pub fn path_of(&self, node: &Node) -> Option<Vec<usize>> {
+        if std::ptr::eq(self, node) {
+            return Some(vec![]);
+        }
+        self.children.iter().enumerate().find_map(|(i, c)| {
+            c.path_of(node).map(|mut path| {
+                path.insert(0, i);
+                path
+            })
+        })
+    }
+
+    /// A path based id such as `0/2/1`, stable under edits that keep the
+    /// tree's structure.
+    pub fn path_id(&self, node: &Node) -> Option<String> {
+        self.path_of(node).map(|path| {
+            path.iter()
+                .map(|i| i.to_string())
+                .collect::<Vec<_>>()
+                .join("/")
+        })
+    }
+
+    pub fn at_path(&self, path: &[usize]) -> Option<&Node> {
+        match path.split_first() {
+            Some((i, rest)) => self.children.get(*i).and_then(|c| c.at_path(rest)),
+            None => Some(self),
+        }
+    }
+
+    pub fn at_path_mut(&mut self, path: &[usize]) -> Option<&mut Node> {
+        match path.split_first() {
+            Some((i, rest)) => self.children.get_mut(*i).and_then(|c| c.at_path_mut(rest)),
+            None => Some(self),
+        }
+    }
+
+    /// Swap the node at `path` (as from `path_of`) for `new`, returning the
+    /// node it replaced, or `None` if there is no node at `path`.
+    pub fn replace(&mut self, path: &[usize], new: Node) -> Option<Node> {
+        let node = self.at_path_mut(path)?;
+        Some(std::mem::replace(node, new))
+    }
+
+    /// Find the deepest node whose span contains `offset`. Offsets that fall
+    /// between children (e.g. on a marker) resolve to the enclosing node.
+    pub fn node_at(&self, offset: usize) -> Option<&Node> {
+        let (start, end) = self.span;
+        if offset < start || offset >= end {
+            return None;
+        }
+        let i = self.children.partition_point(|c| c.span.1 <= offset);
+        match self.children.get(i).and_then(|c| c.node_at(offset)) {
+            Some(n) => Some(n),
+            None => Some(self),
+        }
+    }
+}
+
+// Offset of the start of the line `offset` is on.
+fn line_start(source: &str, offset: usize) -> usize {
+    source[..offset].rfind('\n').map_or(0, |i| i + 1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BlockToken, Section};
+    use crate::markdown::{block_outline, mdast, parse, parse_bounded, Kind, Node};
+    use std::ops::Range;
+
+    fn first_leaf(node: &Node) -> &Node {
+        match node.children.first() {
+            Some(c) => first_leaf(c),
+            None => node,
+        }
+    }
+
+    #[test]
+    fn test_parent() {
+        let doc = parse("> * A\n>   * B");
+        let text = first_leaf(&doc);
+        assert_eq!(text.kind, Kind::Plaintext);
+
+        let mut kinds = vec![];
+        let mut node = text;
+        while let Some(p) = doc.parent(node) {
+            kinds.push(&p.kind);
+            node = p;
+        }
+        assert!(std::ptr::eq(node, &doc));
+        assert_eq!(
+            kinds,
+            vec![
+                &Kind::Paragraph,
+                &Kind::ListItem((2, 3)),
+                &Kind::UnorderedList(false),
+                &Kind::BlockQuote,
+                &Kind::Document,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parent_of_root() {
+        let doc = parse("# Hello");
+        assert_eq!(doc.parent(&doc), None);
+        let other = parse("# Hello");
+        assert_eq!(doc.parent(&other.children[0]), None);
+    }
+
+    #[test]
+    fn test_node_at() {
+        let doc = parse("> * A\n>   * B\n\n# Title");
+
+        let text = doc.node_at(12).unwrap();
+        assert_eq!((&text.kind, text.span), (&Kind::Plaintext, (12, 13)));
+        assert_eq!(doc.parent(text).unwrap().kind, Kind::Paragraph);
+
+        // The nested marker is outside the nested item's span
+        let item = doc.node_at(10).unwrap();
+        assert_eq!((&item.kind, item.span), (&Kind::ListItem((2, 3)), (4, 14)));
+
+        // Between blocks resolves to the document itself
+        let between = doc.node_at(15).unwrap();
+        assert!(std::ptr::eq(between, &doc));
+
+        assert_eq!(doc.node_at(22), None);
+    }
+
+    #[test]
+    fn test_path_id() {
+        let a = parse("# A\n\n* B\n* C");
+        let b = parse("# Hello\n\n* World\n* Again");
+        let c_a = a.node_at(11).unwrap();
+        let c_b = b.node_at(20).unwrap();
+
+        assert_eq!(a.path_id(c_a), Some("2/1/0/0".into()));
+        assert_eq!(a.path_id(c_a), b.path_id(c_b));
+        assert_eq!(a.path_id(&a), Some("".into()));
+        assert_eq!(a.path_id(c_b), None);
+
+        let path = a.path_of(c_a).unwrap();
+        assert!(std::ptr::eq(a.at_path(&path).unwrap(), c_a));
+        assert_eq!(a.at_path(&[9]), None);
+    }
+
+    #[test]
+    fn test_replace() {
+        let source = "# A\n\ntext";
+        let mut doc = parse(source);
+        let heading = Node::new_block(
+            Kind::Heading(2),
+            5,
+            9,
+            vec![Node::new(Kind::Plaintext, 5, 9)],
+        );
+
+        let old = doc.replace(&[2], heading);
+        assert_eq!(old.map(|n| n.kind), Some(Kind::Paragraph));
+        assert_eq!(doc.at_path(&[2]).map(|n| n.kind), Some(Kind::Heading(2)));
+        let root: serde_json::Value = serde_json::from_str(&mdast::render(source, doc)).unwrap();
+        assert_eq!(root["children"][1]["type"], "heading");
+        assert_eq!(root["children"][1]["children"][0]["value"], "text");
+
+        let mut doc = parse(source);
+        assert_eq!(doc.replace(&[2, 5], Node::new(Kind::Empty, 0, 0)), None);
+        assert_eq!(doc, parse(source));
+    }
+
+    #[test]
+    fn test_remove_empty() {
+        let source = "a\n\n* b\n* \n\nc";
+        let mut doc = parse(source);
+        // As a transform would leave a paragraph whose only content it removed
+        doc.children[0].children.clear();
+        doc.remove_empty();
+
+        assert_eq!(doc.children.len(), 4);
+        assert_eq!(doc.children[0].kind, Kind::Empty);
+        assert_eq!(doc.children[1].children.len(), 1);
+        assert_eq!(doc.at_path(&[1, 0, 0, 0]).map(|n| n.span), Some((5, 6)));
+
+        let mut doc = parse("1. ");
+        doc.remove_empty();
+        assert!(doc.children.is_empty());
+
+        // Blank line blocks are left alone, even when emptied
+        let mut doc = parse(source);
+        doc.children[1].children.clear();
+        doc.remove_empty();
+        assert_eq!(doc.children.len(), 5);
+    }
+
+    #[test]
+    fn test_append() {
+        let a = "# A\n* B\n";
+        let b = "# C\n* D";
+        let mut doc = parse(a);
+        doc.append(parse(b), a.len());
+        assert_eq!(doc, parse([a, b].concat()));
+
+        let headings = doc
+            .children
+            .iter()
+            .filter(|n| matches!(n.kind, Kind::Heading(..)))
+            .map(|n| n.span)
+            .collect::<Vec<_>>();
+        assert_eq!(headings, vec![(2, 3), (10, 11)]);
+
+        let mut empty = parse("");
+        empty.append(parse(b), 0);
+        assert_eq!(empty, parse(b));
+    }
+
+    #[test]
+    fn test_section_text() {
+        let source =
+            "# T\n\n## A\n\nintro text\n\n- one\n- two\n\n### A1\n\ndeep\n\n## B\n\nother\n\n> q1\n> q2";
+        let doc = parse(source);
+        let a = &doc.children[2];
+        let b = &doc.children[12];
+
+        assert_eq!(
+            doc.section_text(a, source),
+            Some("intro text\none\ntwo\nA1\ndeep".into())
+        );
+        assert_eq!(doc.section_text(b, source), Some("other\nq1\nq2".into()));
+        assert_eq!(doc.section_text(&doc.children[4], source), None);
+        assert_eq!(doc.section_text(&parse(source).children[2], source), None);
+    }
+
+    #[test]
+    fn test_section_path() {
+        let source = "# Chapter 1\n\n## Installation\n\n### Linux\n\ntext\n\n## Usage\n\nmore";
+        let doc = parse(source);
+        let text = source.find("text").unwrap();
+        let more = source.find("more").unwrap();
+
+        assert_eq!(
+            doc.section_path(source, text),
+            vec!["Chapter 1", "Installation", "Linux"]
+        );
+        assert_eq!(doc.section_path(source, more), vec!["Chapter 1", "Usage"]);
+        assert_eq!(doc.section_path(source, 0), vec!["Chapter 1"]);
+        assert!(parse("a\n\n> # b").section_path("a\n\n> # b", 9).is_empty());
+
+        // The heading's own marker is inside its section
+        let marker = source.find("## Usage").unwrap();
+        assert_eq!(doc.section_path(source, marker), vec!["Chapter 1", "Usage"]);
+        assert_eq!(
+            doc.section_path(source, marker - 1),
+            vec!["Chapter 1", "Installation", "Linux"]
+        );
+    }
+
+    #[test]
+    fn test_take_title() {
+        let source = "intro\n# Title\n\n## A\n\n# Other";
+        let mut doc = parse(source);
+        assert_eq!(doc.take_title(source), Some("Title".into()));
+        assert_eq!(
+            doc.children
+                .iter()
+                .filter(|n| n.kind == Kind::Heading(1))
+                .count(),
+            1
+        );
+        assert_eq!(doc.section_path(source, source.len()), vec!["Other"]);
+
+        let mut doc = parse("## A\n\ntext");
+        assert_eq!(doc.take_title("## A\n\ntext"), None);
+        assert_eq!(doc, parse("## A\n\ntext"));
+    }
+
+    #[test]
+    fn test_description() {
+        let source = "# Title\n\nThe quick brown fox\njumps over the lazy dog.\n\nMore.";
+        let doc = parse(source);
+
+        assert_eq!(
+            doc.description(source, 100),
+            Some("The quick brown fox jumps over the lazy dog.".into())
+        );
+        assert_eq!(
+            doc.description(source, 22),
+            Some("The quick brown fox…".into())
+        );
+        assert_eq!(
+            doc.description(source, 20),
+            Some("The quick brown fox…".into())
+        );
+        assert_eq!(doc.description(source, 19), Some("The quick brown…".into()));
+        assert_eq!(
+            parse("Ünïcödé").description("Ünïcödé", 4),
+            Some("Ünï…".into())
+        );
+        assert_eq!(parse("# Title").description("# Title", 10), None);
+
+        // The ellipsis counts towards the budget
+        assert_eq!(doc.description(source, 1), Some("…".into()));
+        assert_eq!(doc.description(source, 0), None);
+        for max in 1..50 {
+            let d = doc.description(source, max).unwrap();
+            assert!(d.chars().count() <= max, "{:?} over {}", d, max);
+        }
+    }
+
+    #[test]
+    fn test_search() {
+        let source = "# a # heading\n\n- a **bold** item\n\n> bold";
+        let doc = parse(source);
+
+        assert_eq!(doc.search("bold", source), vec![(21, 25), (36, 40)]);
+        assert_eq!(doc.search("#", source), vec![(4, 5)]);
+        assert_eq!(doc.search("-", source), vec![]);
+        assert_eq!(doc.search("", source), vec![]);
+
+        let source = "> a b\n> b";
+        let doc = parse(source);
+        assert_eq!(doc.search(">", source), vec![]);
+        assert_eq!(doc.search("b", source), vec![(4, 5), (8, 9)]);
+        assert_eq!(doc.search("b\nb", source), vec![]);
+    }
+
+    #[test]
+    fn test_event_log() {
+        assert_eq!(
+            parse("## Hello\n\n1. a").event_log(),
+            vec![
+                "enter document 0..14",
+                "enter heading(2) 3..8",
+                "text 3..8",
+                "exit heading",
+                "enter empty 8..10",
+                "empty_line 8..9",
+                "empty_line 9..10",
+                "exit empty",
+                "enter ordered_list(tight) 13..14",
+                "enter list_item(10..12) 13..14",
+                "enter paragraph 13..14",
+                "text 13..14",
+                "exit paragraph",
+                "exit list_item",
+                "exit ordered_list",
+                "exit document",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_structural_eq() {
+        let source = "# A\n\n> * B\n>   * C";
+        assert!(parse(source).structural_eq(&parse(source), false));
+        assert!(parse(source).structural_eq(&parse(source), true));
+
+        let longer = "# AAA\n\n> * BB\n>   * CCCC";
+        assert!(!parse(source).structural_eq(&parse(longer), false));
+        assert!(parse(source).structural_eq(&parse(longer), true));
+
+        assert!(!parse("# A").structural_eq(&parse("## A"), true));
+        assert!(!parse("* A").structural_eq(&parse("1. A"), true));
+        assert!(!parse("A").structural_eq(&parse("A\n\nB"), true));
+    }
+
+    #[test]
+    fn test_descendants() {
+        let doc = parse("# A\n> B");
+        let kinds = doc.descendants().map(|n| n.kind.name()).collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                "Heading",
+                "Plaintext",
+                "Empty",
+                "EmptyLine",
+                "BlockQuote",
+                "Paragraph",
+                "Plaintext",
+            ]
+        );
+        assert_eq!(parse("").descendants().next(), None);
+    }
+
+    #[test]
+    fn test_kind_histogram() {
+        let doc = parse("# A\n\nB\n\n## C\n\n* D\n* E\n\n> F");
+        let counts = doc.kind_histogram();
+        assert_eq!(counts["Heading"], 2);
+        assert_eq!(counts["Paragraph"], 4);
+        assert_eq!(counts["UnorderedList"], 1);
+        assert_eq!(counts["ListItem"], 2);
+        assert_eq!(counts["BlockQuote"], 1);
+        assert_eq!(counts.get("OrderedList"), None);
+        assert_eq!(counts.get("Document"), None);
+    }
+
+    #[test]
+    fn test_truncate() {
+        let source = "# A\n\n* B\n* C";
+        let mut doc = parse(source);
+        let total = doc.descendants().count() + 1;
+        assert!(!doc.truncate(total));
+        assert_eq!(doc, parse(source));
+
+        assert!(doc.truncate(4));
+        let kinds = doc.descendants().map(|n| n.kind.name()).collect::<Vec<_>>();
+        assert_eq!(kinds, vec!["Heading", "Plaintext", "Empty"]);
+
+        assert!(doc.truncate(0));
+        assert!(doc.children.is_empty());
+    }
+
+    #[test]
+    fn test_parse_bounded() {
+        let source = "A\n\n".repeat(1000);
+        let (doc, truncated) = parse_bounded(&source, 100);
+        assert!(truncated);
+        assert_eq!(doc.descendants().count(), 99);
+
+        let (doc, truncated) = parse_bounded("A", 100);
+        assert!(!truncated);
+        assert_eq!(doc, parse("A"));
+
+        // The parse stops early instead of running over the whole source
+        let source = "text\n\n".repeat(100_000);
+        let (doc, truncated) = parse_bounded(&source, 10);
+        assert!(truncated);
+        assert_eq!(doc.descendants().count(), 9);
+        assert!(doc.span.1 < 1000);
+
+        let source = "# A\n\n> b\n\n* c\n* d\n\ntext";
+        assert_eq!(parse_bounded(source, 1000), (parse(source), false));
+
+        // A cut container ends at its last kept child
+        let source = "* a\n\n* b\n\n* c\n";
+        let (doc, truncated) = parse_bounded(source, 6);
+        assert!(truncated);
+        let list = &doc.children[0];
+        assert_eq!(list.children.len(), 1);
+        assert_eq!(list.span.1, list.children[0].span.1);
+        assert_eq!(doc.span.1, list.span.1);
+    }
+
+    #[test]
+    fn test_block_sources() {
+        let source = "# A\n\n> b\n> c\n\n* d\n* e\n\ntext\n";
+        let doc = parse(source);
+        let ranges = doc.block_sources(source);
+        let blocks = ranges
+            .iter()
+            .map(|r| &source[r.clone()])
+            .collect::<Vec<_>>();
+
+        assert_eq!(ranges.len(), doc.children.len());
+        assert_eq!(
+            blocks,
+            vec![
+                "# A\n",
+                "\n",
+                "> b\n> c\n",
+                "\n",
+                "* d\n* e\n",
+                "\n",
+                "text\n"
+            ]
+        );
+
+        for source in ["", "A \n", "\n\nA\n# B\n- c\n\n\n> d"] {
+            let ranges = parse(source).block_sources(source);
+            let blocks = ranges.into_iter().map(|r| &source[r]).collect::<String>();
+            assert_eq!(blocks, source);
+        }
+    }
+
+    #[test]
+    fn test_block_outline() {
+        let outline = block_outline("# A\n\n- b\n  - c\n\n> ## d\n> e");
+        let block = |kind: Kind, lines: Range<usize>, indent: usize| BlockToken {
+            kind,
+            lines,
+            indent,
+        };
+
+        assert_eq!(
+            outline,
+            vec![
+                block(Kind::Heading(1), 1..2, 0),
+                block(Kind::UnorderedList(false), 3..5, 0),
+                block(Kind::ListItem((5, 6)), 3..5, 0),
+                block(Kind::Paragraph, 3..4, 0),
+                block(Kind::UnorderedList(false), 4..5, 2),
+                block(Kind::ListItem((11, 12)), 4..5, 2),
+                block(Kind::Paragraph, 4..5, 2),
+                block(Kind::BlockQuote, 6..8, 0),
+                block(Kind::Heading(2), 6..7, 0),
+                block(Kind::Paragraph, 7..8, 0),
+            ]
+        );
+        assert!(block_outline("").is_empty());
+    }
+
+    #[test]
+    fn test_max_heading_level() {
+        let doc = parse("# A\n\n## B\n\n> #### D\n\n### C");
+        assert_eq!(doc.max_heading_level(), Some(4));
+        assert_eq!(parse("text\n\n* item").max_heading_level(), None);
+    }
+
+    #[test]
+    fn test_h1_count() {
+        assert_eq!(parse("# A\n\n## B\n\n# C\n\n> # D").h1_count(), 3);
+        assert_eq!(parse("* # A").h1_count(), 1);
+        assert_eq!(parse("## A").h1_count(), 0);
+    }
+
+    #[test]
+    fn test_heading_level_skips() {
+        assert_eq!(parse("# A\n\n### B").heading_level_skips(), vec![(1, 3)]);
+        assert_eq!(
+            parse("# A\n\n## B\n\n### C\n\n# D").heading_level_skips(),
+            vec![]
+        );
+        assert_eq!(
+            parse("## A\n\n> #### B\n\n# C\n\n###### D").heading_level_skips(),
+            vec![(2, 4), (1, 6)]
+        );
+    }
+
+    #[test]
+    fn test_sections() {
+        let source = "intro\n# A\n\n## B\n\ntext\n\n## C\n\n# D\n";
+        let sections = parse(source).sections(source);
+        let section = |level: usize, title: &str, range: Range<usize>| Section {
+            level,
+            title: title.into(),
+            range,
+        };
+
+        assert_eq!(
+            sections,
+            vec![
+                section(1, "A", 6..29),
+                section(2, "B", 11..23),
+                section(2, "C", 23..29),
+                section(1, "D", 29..33),
+            ]
+        );
+        assert_eq!(&source[sections[1].range.clone()], "## B\n\ntext\n\n");
+        assert!(parse("text").sections("text").is_empty());
+    }
+}